@@ -0,0 +1,695 @@
+//! Steam's BBCode dialect, as used in Workshop descriptions
+
+/// Tags we understand, anything else in brackets is left as text
+const TAGS: &[&str] = &[
+	"h1", "h2", "h3", "b", "u", "i", "strike", "spoiler", "noparse", "hr", "url", "list", "olist", "*", "quote", "code", "table", "tr", "th", "td",
+	"img", "previewyoutube",
+];
+/// Deepest nesting of tags we build, deeper tags are left as text so that rendering can't run out of stack
+const MAX_DEPTH: usize = 32;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DescriptionFormat {
+	/// The BBCode as Steam returned it
+	#[default]
+	Raw,
+	Plain,
+	/// HTML with only safe tags and http(s) links
+	Html,
+	/// A table of paragraphs, links and images
+	Structured,
+}
+impl DescriptionFormat {
+	pub fn parse(format: &str) -> Option<Self> {
+		match format.to_ascii_lowercase().as_str() {
+			"raw" | "bbcode" => Some(DescriptionFormat::Raw),
+			"plain" | "text" => Some(DescriptionFormat::Plain),
+			"html" => Some(DescriptionFormat::Html),
+			"structured" => Some(DescriptionFormat::Structured),
+			_ => None
+		}
+	}
+}
+
+#[derive(Debug)]
+enum Node {
+	Text(String),
+	Tag {
+		name: &'static str,
+		arg: Option<String>,
+		children: Vec<Node>,
+	},
+}
+
+enum Token<'a> {
+	Open(&'static str, Option<&'a str>),
+	Close(&'static str),
+}
+impl<'a> Token<'a> {
+	fn parse(tag: &'a str) -> Option<Self> {
+		let known = |name: &str| TAGS.iter().copied().find(|known| known.eq_ignore_ascii_case(name.trim()));
+
+		if let Some(name) = tag.strip_prefix('/') {
+			return known(name).map(Token::Close);
+		}
+
+		let (name, arg) = match tag.split_once('=') {
+			Some((name, arg)) => (name, Some(arg.trim().trim_matches(|c| c == '"' || c == '\''))),
+			None => (tag, None),
+		};
+		known(name).map(|name| Token::Open(name, arg))
+	}
+}
+
+fn parse(src: &str) -> Vec<Node> {
+	// The bottom frame is the document itself
+	let mut stack: Vec<(&'static str, Option<String>, Vec<Node>)> = vec![("", None, Vec::new())];
+	let mut text = String::new();
+
+	fn pop(stack: &mut Vec<(&'static str, Option<String>, Vec<Node>)>) {
+		let (name, arg, children) = stack.pop().unwrap();
+		stack.last_mut().unwrap().2.push(Node::Tag { name, arg, children });
+	}
+
+	fn flush(stack: &mut [(&'static str, Option<String>, Vec<Node>)], text: &mut String) {
+		if !text.is_empty() {
+			stack.last_mut().unwrap().2.push(Node::Text(std::mem::take(text)));
+		}
+	}
+
+	let mut rest = src;
+	while let Some(start) = rest.find('[') {
+		text.push_str(&rest[..start]);
+		rest = &rest[start..];
+
+		let token = rest.find(']').and_then(|end| Some((Token::parse(&rest[1..end])?, end)));
+		let (token, end) = match token {
+			Some(token) => token,
+			None => {
+				text.push('[');
+				rest = &rest[1..];
+				continue;
+			}
+		};
+		let source = &rest[..=end];
+		rest = &rest[end + 1..];
+
+		flush(&mut stack, &mut text);
+
+		match token {
+			// Everything up to the closing tag is taken literally
+			Token::Open(name @ ("noparse" | "code"), arg) => {
+				let close = format!("[/{}]", name);
+				let (content, after) = match rest.to_ascii_lowercase().find(&close) {
+					Some(end) => (&rest[..end], &rest[end + close.len()..]),
+					None => (rest, ""),
+				};
+				rest = after;
+
+				let children = if content.is_empty() { Vec::new() } else { vec![Node::Text(content.to_owned())] };
+				stack.last_mut().unwrap().2.push(Node::Tag { name, arg: arg.map(ToOwned::to_owned), children });
+			},
+
+			// Steam uses both [hr] and [hr][/hr]
+			Token::Open("hr", _) => {
+				stack.last_mut().unwrap().2.push(Node::Tag { name: "hr", arg: None, children: Vec::new() });
+			},
+
+			Token::Open(name, arg) => {
+				// List items are closed by the next item
+				if name == "*" && stack.last().unwrap().0 == "*" {
+					pop(&mut stack);
+				}
+				if stack.len() > MAX_DEPTH {
+					text.push_str(source);
+					continue;
+				}
+				stack.push((name, arg.map(ToOwned::to_owned), Vec::new()));
+			},
+
+			// Closing tags that were never opened are dropped
+			Token::Close(name) => {
+				if let Some(pos) = stack.iter().skip(1).rposition(|(open, ..)| *open == name) {
+					while stack.len() > pos + 1 {
+						pop(&mut stack);
+					}
+				}
+			},
+		}
+	}
+	text.push_str(rest);
+	flush(&mut stack, &mut text);
+
+	while stack.len() > 1 {
+		pop(&mut stack);
+	}
+	stack.pop().unwrap().2
+}
+
+/// Only http(s) links make it into rendered output
+fn safe_url(url: &str) -> Option<&str> {
+	let url = url.trim();
+	let lower = url.to_ascii_lowercase();
+	if lower.starts_with("https://") || lower.starts_with("http://") {
+		Some(url)
+	} else {
+		None
+	}
+}
+
+/// Concatenated text of some nodes, ignoring formatting
+fn text_of(nodes: &[Node]) -> String {
+	let mut text = String::new();
+	for node in nodes {
+		match node {
+			Node::Text(s) => text.push_str(s),
+			Node::Tag { children, .. } => text.push_str(&text_of(children)),
+		}
+	}
+	text
+}
+
+/// Output with a cap on the number of characters, markup included
+struct Output {
+	out: String,
+	/// Characters left, `None` for no limit
+	remaining: Option<usize>,
+	truncated: bool,
+	/// The source's line break after a block element is already implied by the element
+	after_block: bool,
+}
+impl Output {
+	fn new(max_len: usize) -> Self {
+		Output {
+			out: String::new(),
+			remaining: if max_len == 0 { None } else { Some(max_len) },
+			truncated: false,
+			after_block: false,
+		}
+	}
+
+	/// Takes `len` characters off the cap, or marks the output truncated if they don't fit
+	fn reserve(&mut self, len: usize) -> bool {
+		match self.remaining.as_mut() {
+			Some(remaining) if *remaining < len => {
+				self.truncated = true;
+				false
+			},
+			Some(remaining) => {
+				*remaining -= len;
+				true
+			},
+			None => true,
+		}
+	}
+
+	/// Returns the part of `text` that fits, each character costing `weight(c)` once written out
+	fn text<'a>(&mut self, text: &'a str, weight: fn(char) -> usize) -> &'a str {
+		if self.remaining.is_none() {
+			return text;
+		}
+
+		for (i, c) in text.char_indices() {
+			if !self.reserve(weight(c)) {
+				return &text[..i];
+			}
+		}
+		text
+	}
+
+	/// Appends markup if it fits
+	fn markup(&mut self, markup: &str) -> bool {
+		if !self.reserve(markup.chars().count()) {
+			return false;
+		}
+		self.out.push_str(markup);
+		true
+	}
+
+	/// Appends an opening tag if it fits along with its closing tag, so the closing tag can always be written
+	fn open(&mut self, open: &str, close: &str) -> bool {
+		if !self.reserve(open.chars().count() + close.chars().count()) {
+			return false;
+		}
+		self.out.push_str(open);
+		true
+	}
+
+	/// Appends a closing tag that was paid for by [`open`](Self::open)
+	fn close(&mut self, close: &str) {
+		self.out.push_str(close);
+	}
+
+	/// Makes sure the output ends with at least `n` line breaks
+	fn newlines(&mut self, n: usize) {
+		if self.out.is_empty() {
+			return;
+		}
+		let have = self.out.len() - self.out.trim_end_matches('\n').len();
+		for _ in have..n {
+			if !self.markup("\n") {
+				return;
+			}
+		}
+	}
+}
+
+fn plain(nodes: &[Node], out: &mut Output, container: bool) {
+	for node in nodes {
+		if out.truncated {
+			return;
+		}
+
+		let (name, arg, children) = match node {
+			// Whitespace between list items and table cells is just source formatting
+			Node::Text(text) if container && text.trim().is_empty() => continue,
+			Node::Text(text) => {
+				let text = out.text(text, |_| 1);
+				out.out.push_str(text);
+				continue;
+			},
+			Node::Tag { name, arg, children } => (*name, arg, children),
+		};
+
+		match name {
+			"h1" | "h2" | "h3" | "quote" | "code" => {
+				out.newlines(2);
+				plain(children, out, false);
+				out.newlines(2);
+			},
+
+			"hr" => out.newlines(2),
+
+			"url" => {
+				let start = out.out.len();
+				plain(children, out, false);
+				if let Some(url) = arg.as_deref().and_then(safe_url) {
+					if out.out[start..].trim() != url && !out.truncated {
+						out.markup(&format!(" ({})", url));
+					}
+				}
+			},
+
+			"list" | "olist" => {
+				out.newlines(1);
+				let mut n = 0;
+				for child in children {
+					if out.truncated {
+						break;
+					}
+					match child {
+						Node::Tag { name: "*", children, .. } => {
+							n += 1;
+							out.newlines(1);
+							if !out.markup(&if name == "olist" { format!("{}. ", n) } else { "• ".to_owned() }) {
+								break;
+							}
+							plain(children, out, false);
+							out.out.truncate(out.out.trim_end().len());
+						},
+						Node::Text(text) if text.trim().is_empty() => {},
+						other => plain(std::slice::from_ref(other), out, false),
+					}
+				}
+				out.newlines(1);
+			},
+
+			"table" => {
+				out.newlines(2);
+				plain(children, out, true);
+				out.newlines(2);
+			},
+			"tr" => {
+				out.newlines(1);
+				plain(children, out, true);
+			},
+			"th" | "td" => {
+				if !out.out.ends_with('\n') {
+					out.markup("\t");
+				}
+				plain(children, out, false);
+			},
+
+			// Embedded media has no text
+			"img" | "previewyoutube" => {},
+
+			_ => plain(children, out, false),
+		}
+	}
+}
+
+fn escape_char(c: char) -> Option<&'static str> {
+	match c {
+		'&' => Some("&amp;"),
+		'<' => Some("&lt;"),
+		'>' => Some("&gt;"),
+		'"' => Some("&quot;"),
+		'\'' => Some("&#39;"),
+		'\n' => Some("<br>"),
+		'\r' => Some(""),
+		_ => None,
+	}
+}
+
+/// Length of a character once escaped
+fn escaped_len(c: char) -> usize {
+	escape_char(c).map_or(1, str::len)
+}
+
+fn escape(out: &mut String, text: &str) {
+	for c in text.chars() {
+		match escape_char(c) {
+			Some(escaped) => out.push_str(escaped),
+			None => out.push(c),
+		}
+	}
+}
+
+fn html(nodes: &[Node], out: &mut Output, container: bool) {
+	for node in nodes {
+		if out.truncated {
+			return;
+		}
+
+		let (name, arg, children) = match node {
+			Node::Text(text) if container && text.trim().is_empty() => continue,
+			Node::Text(text) => {
+				let text = if std::mem::take(&mut out.after_block) {
+					text.strip_prefix("\r\n").or_else(|| text.strip_prefix('\n')).unwrap_or(text)
+				} else {
+					text
+				};
+				let text = out.text(text, escaped_len);
+				escape(&mut out.out, text);
+				continue;
+			},
+			Node::Tag { name, arg, children } => (*name, arg, children),
+		};
+
+		let element = match name {
+			"h1" | "h2" | "h3" | "b" | "u" | "i" | "table" | "tr" | "th" | "td" => name,
+			"strike" => "s",
+			"quote" => "blockquote",
+			"code" => "pre",
+			"list" => "ul",
+			"olist" => "ol",
+			"*" => "li",
+
+			"spoiler" => {
+				if out.open("<span class=\"spoiler\">", "</span>") {
+					html(children, out, false);
+					out.close("</span>");
+				}
+				continue;
+			},
+
+			"hr" => {
+				out.markup("<hr>");
+				out.after_block = true;
+				continue;
+			},
+
+			"url" => {
+				let url = arg.clone().unwrap_or_else(|| text_of(children));
+				match safe_url(&url) {
+					Some(url) => {
+						let mut href = String::new();
+						escape(&mut href, url);
+						if out.open(&format!("<a href=\"{}\" rel=\"noopener noreferrer\" target=\"_blank\">", href), "</a>") {
+							html(children, out, false);
+							out.close("</a>");
+						}
+					},
+					None => html(children, out, false),
+				}
+				continue;
+			},
+
+			"img" => {
+				let src = arg.clone().unwrap_or_else(|| text_of(children));
+				if let Some(src) = safe_url(&src) {
+					let mut escaped = String::new();
+					escape(&mut escaped, src);
+					out.markup(&format!("<img src=\"{}\">", escaped));
+				}
+				continue;
+			},
+
+			// [previewyoutube=id;full][/previewyoutube]
+			"previewyoutube" => {
+				if let Some(id) = arg.as_deref().map(|arg| arg.split(';').next().unwrap_or_default()) {
+					if !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+						out.markup(&format!("<a href=\"https://www.youtube.com/watch?v={0}\" rel=\"noopener noreferrer\" target=\"_blank\">https://www.youtube.com/watch?v={0}</a>", id));
+					}
+				}
+				continue;
+			},
+
+			_ => {
+				html(children, out, false);
+				continue;
+			},
+		};
+
+		let close = format!("</{}>", element);
+		if !out.open(&format!("<{}>", element), &close) {
+			return;
+		}
+		out.after_block = false;
+		html(children, out, matches!(name, "list" | "olist" | "table" | "tr"));
+		if name == "*" {
+			while out.out.ends_with("<br>") {
+				out.out.truncate(out.out.len() - "<br>".len());
+			}
+		}
+		out.close(&close);
+		out.after_block = matches!(name, "h1" | "h2" | "h3" | "quote" | "code" | "list" | "olist" | "table");
+	}
+}
+
+fn collect_links(nodes: &[Node], links: &mut Vec<(String, String)>, images: &mut Vec<String>) {
+	for node in nodes {
+		if let Node::Tag { name, arg, children } = node {
+			match *name {
+				"url" => {
+					let text = text_of(children);
+					let url = arg.clone().unwrap_or_else(|| text.clone());
+					if let Some(url) = safe_url(&url) {
+						links.push((text.trim().to_owned(), url.to_owned()));
+					}
+				},
+				"img" => {
+					let src = arg.clone().unwrap_or_else(|| text_of(children));
+					if let Some(src) = safe_url(&src) {
+						images.push(src.to_owned());
+					}
+				},
+				_ => collect_links(children, links, images),
+			}
+		}
+	}
+}
+
+/// Tidies up blank lines and marks truncation
+fn finish(out: Output) -> (String, bool) {
+	let mut text = String::with_capacity(out.out.len());
+	let mut blank = 0;
+	for line in out.out.lines() {
+		let line = line.trim_end();
+		if line.is_empty() {
+			blank += 1;
+			continue;
+		}
+		if !text.is_empty() {
+			text.push_str(if blank > 0 { "\n\n" } else { "\n" });
+		}
+		text.push_str(line);
+		blank = 0;
+	}
+	if out.truncated {
+		text.push('…');
+	}
+	(text, out.truncated)
+}
+
+/// Caps the raw BBCode to `max_len` characters, 0 for no limit
+pub fn to_raw(src: &str, max_len: usize) -> String {
+	match src.char_indices().nth(max_len).filter(|_| max_len > 0) {
+		Some((end, _)) => format!("{}…", &src[..end]),
+		None => src.to_owned(),
+	}
+}
+
+/// Renders to plain text of at most `max_len` characters, 0 for no limit
+pub fn to_plain(src: &str, max_len: usize) -> String {
+	let mut out = Output::new(max_len);
+	plain(&parse(src), &mut out, false);
+	finish(out).0
+}
+
+/// Renders to HTML of at most `max_len` characters, markup included, 0 for no limit
+///
+/// Text is escaped and only http(s) URLs are used for links and images. Tags that are opened are always closed.
+pub fn to_html(src: &str, max_len: usize) -> String {
+	let mut out = Output::new(max_len);
+	html(&parse(src), &mut out, false);
+
+	let mut html = out.out.trim().trim_start_matches("<br>").to_owned();
+	if out.truncated {
+		html.push('…');
+	}
+	html
+}
+
+#[derive(Debug, Default)]
+pub struct Structured {
+	pub paragraphs: Vec<String>,
+	/// Link text and URL
+	pub links: Vec<(String, String)>,
+	pub images: Vec<String>,
+	pub truncated: bool,
+}
+
+/// Splits the description into paragraphs of plain text, with at most `max_len` characters in total, 0 for no limit
+pub fn to_structured(src: &str, max_len: usize) -> Structured {
+	let nodes = parse(src);
+
+	let mut out = Output::new(max_len);
+	plain(&nodes, &mut out, false);
+	let (text, truncated) = finish(out);
+
+	let mut structured = Structured {
+		paragraphs: text.split("\n\n").filter(|paragraph| !paragraph.is_empty()).map(ToOwned::to_owned).collect(),
+		truncated,
+		..Default::default()
+	};
+	collect_links(&nodes, &mut structured.links, &mut structured.images);
+	structured
+}
+
+/// Pushes the description in the requested format
+pub unsafe fn push(lua: gmod::lua::State, src: &[u8], format: DescriptionFormat, max_len: usize) {
+	if format == DescriptionFormat::Raw && max_len == 0 {
+		return lua.push_binary_string(src);
+	}
+
+	let src = String::from_utf8_lossy(src);
+	match format {
+		DescriptionFormat::Raw => lua.push_string(&to_raw(&src, max_len)),
+		DescriptionFormat::Plain => lua.push_string(&to_plain(&src, max_len)),
+		DescriptionFormat::Html => lua.push_string(&to_html(&src, max_len)),
+		DescriptionFormat::Structured => {
+			let structured = to_structured(&src, max_len);
+
+			lua.new_table();
+
+			lua.create_table(structured.paragraphs.len() as _, 0);
+			for (i, paragraph) in structured.paragraphs.iter().enumerate() {
+				lua.push_string(paragraph);
+				lua.raw_seti(-2, (i + 1) as _);
+			}
+			lua.set_field(-2, lua_string!("paragraphs"));
+
+			lua.create_table(structured.links.len() as _, 0);
+			for (i, (text, url)) in structured.links.iter().enumerate() {
+				lua.create_table(0, 2);
+
+				lua.push_string(text);
+				lua.set_field(-2, lua_string!("text"));
+
+				lua.push_string(url);
+				lua.set_field(-2, lua_string!("url"));
+
+				lua.raw_seti(-2, (i + 1) as _);
+			}
+			lua.set_field(-2, lua_string!("links"));
+
+			lua.create_table(structured.images.len() as _, 0);
+			for (i, image) in structured.images.iter().enumerate() {
+				lua.push_string(image);
+				lua.raw_seti(-2, (i + 1) as _);
+			}
+			lua.set_field(-2, lua_string!("images"));
+
+			lua.push_boolean(structured.truncated);
+			lua.set_field(-2, lua_string!("truncated"));
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn nesting() {
+		assert_eq!(to_html("[b]bold [i]both[/i][/b] plain", 0), "<b>bold <i>both</i></b> plain");
+		assert_eq!(to_plain("[list][*]one[*]two [b]three[/b][/list]", 0), "• one\n• two three");
+		assert_eq!(to_html("[olist][*]one[*]two[/olist]", 0), "<ol><li>one</li><li>two</li></ol>");
+		assert_eq!(to_html("[noparse][b]not bold[/b][/noparse]", 0), "[b]not bold[/b]");
+	}
+
+	#[test]
+	fn unclosed_tags() {
+		assert_eq!(to_html("[b]bold [i]both", 0), "<b>bold <i>both</i></b>");
+		assert_eq!(to_html("[code]x < y", 0), "<pre>x &lt; y</pre>");
+		assert_eq!(to_plain("[b]bold", 0), "bold");
+	}
+
+	#[test]
+	fn mismatched_tags() {
+		// Closing an outer tag closes everything inside it
+		assert_eq!(to_html("[b]bold [i]both[/b] plain[/i]", 0), "<b>bold <i>both</i></b> plain");
+		// Closing tags that were never opened are dropped, unknown tags are text
+		assert_eq!(to_html("plain[/b] [foo]text[/foo]", 0), "plain [foo]text[/foo]");
+		assert_eq!(to_html("[b unclosed", 0), "[b unclosed");
+	}
+
+	#[test]
+	fn url_escaping() {
+		assert_eq!(
+			to_html("[url=https://example.com/?a=1&b=\"2\"&c=3]<link>[/url]", 0),
+			"<a href=\"https://example.com/?a=1&amp;b=&quot;2&quot;&amp;c=3\" rel=\"noopener noreferrer\" target=\"_blank\">&lt;link&gt;</a>"
+		);
+		assert_eq!(
+			to_html("[url]https://example.com/<script>[/url]", 0),
+			"<a href=\"https://example.com/&lt;script&gt;\" rel=\"noopener noreferrer\" target=\"_blank\">https://example.com/&lt;script&gt;</a>"
+		);
+		assert_eq!(to_html("[url=javascript:alert(1)]click[/url]", 0), "click");
+		assert_eq!(to_html("[img]data:image/png;base64,AAAA[/img]", 0), "");
+		assert_eq!(to_plain("[url=https://example.com]site[/url]", 0), "site (https://example.com)");
+	}
+
+	#[test]
+	fn depth_cap() {
+		let src = format!("{}deep{}", "[b]".repeat(MAX_DEPTH + 8), "[/b]".repeat(MAX_DEPTH + 8));
+		let html = to_html(&src, 0);
+		assert!(html.starts_with(&"<b>".repeat(MAX_DEPTH)));
+		assert!(html.contains(&format!("{}deep", "[b]".repeat(8))));
+		assert_eq!(html.matches("<b>").count(), html.matches("</b>").count());
+
+		// Nothing this deep can be rendered recursively
+		let src = "[quote]".repeat(10_000);
+		assert_eq!(to_plain(&src, 0), "[quote]".repeat(10_000 - MAX_DEPTH));
+		to_structured(&src, 0);
+	}
+
+	#[test]
+	fn length_cap() {
+		assert_eq!(to_raw("[b]bold[/b]", 5), "[b]bo…");
+		assert_eq!(to_plain("[b]bold text[/b]", 4), "bold…");
+
+		let html = to_html("[b]bold[/b] and [i]italic[/i]", 16);
+		assert_eq!(html, "<b>bold</b> and…");
+
+		// Escaped characters count at their escaped length
+		assert_eq!(to_html("a & b", 6), "a…");
+		assert_eq!(to_html("a & b", 7), "a &amp;…");
+
+		for max_len in 1..60 {
+			let html = to_html("[list][*][url=https://example.com]one[/url][*]two[/list]", max_len);
+			assert!(html.trim_end_matches('…').chars().count() <= max_len, "{} > {}", html, max_len);
+			assert_eq!(html.matches("<li>").count(), html.matches("</li>").count());
+		}
+	}
+}
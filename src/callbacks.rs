@@ -56,4 +56,10 @@ pub fn pop() {
 			lua.pop();
 		}
 	}
-}
\ No newline at end of file
+}
+
+/// Forgets about any pending callbacks and stops polling for them
+pub fn reset() {
+	CALLBACK_MGR.with(|mgr| mgr.pending.set(1));
+	pop();
+}
@@ -0,0 +1,250 @@
+use crate::{info_cache::InfoCacheConfig, mirror::MirrorConfig, policy::Policy, preflight::PreflightConfig, quota::QuotaConfig, retry::RetryPolicy, scan::ScanConfig, watch::WatchConfig};
+use gmod::lua::{LuaString, LUA_TBOOLEAN, LUA_TNUMBER, LUA_TSTRING, LUA_TTABLE};
+use std::{cell::RefCell, time::Duration};
+
+pub const CONFIG_PATH: &str = "gmsv_workshop/config.json";
+
+/// Longest duration a setting can ask for (a year), so values like `math.huge` can't overflow an `Instant`
+pub const MAX_SECONDS: f64 = 60. * 60. * 24. * 365.;
+
+thread_local! {
+	static CONFIG: RefCell<Config> = RefCell::new(Config::default());
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+	/// Register every successful download with `resource.AddWorkshop`
+	pub addworkshop: bool,
+	/// Retry policy for failed downloads
+	pub retry: RetryPolicy,
+	/// Maximum number of simultaneous Steam downloads, 0 for no limit
+	pub concurrency: u32,
+	/// Per-caller download quotas
+	pub quota: QuotaConfig,
+	/// Size and disk space checks before downloading
+	pub preflight: PreflightConfig,
+	/// Which items we are willing to download at all
+	pub policy: Policy,
+	/// Risk scan of the Lua files in downloaded GMAs
+	pub scan: ScanConfig,
+	/// Keep serving the previous version of an item when an update changes its Lua files, until an admin approves it
+	pub hold_lua_updates: bool,
+	/// Collection to keep the server's content in sync with
+	pub mirror: MirrorConfig,
+	/// Checks for newer versions of the items we have served
+	pub watch: WatchConfig,
+	/// Caching of `steamworks.FileInfo` results
+	pub file_info: InfoCacheConfig,
+}
+impl Config {
+	/// Overrides any known keys with the values in the Lua table at `index`
+	pub unsafe fn apply(&mut self, lua: gmod::lua::State, index: i32) {
+		if let Some(addworkshop) = get_bool(lua, index, lua_string!("addworkshop")) {
+			self.addworkshop = addworkshop;
+		}
+
+		lua.get_field(index, lua_string!("retry"));
+		if lua.lua_type(-1) == LUA_TTABLE {
+			self.retry.apply(lua, lua.get_top());
+		}
+		lua.pop();
+
+		if let Some(concurrency) = get_number(lua, index, lua_string!("concurrency")) {
+			self.concurrency = concurrency.max(0.) as u32;
+		}
+
+		lua.get_field(index, lua_string!("quota"));
+		if lua.lua_type(-1) == LUA_TTABLE {
+			self.quota.apply(lua, lua.get_top());
+		}
+		lua.pop();
+
+		lua.get_field(index, lua_string!("preflight"));
+		if lua.lua_type(-1) == LUA_TTABLE {
+			self.preflight.apply(lua, lua.get_top());
+		}
+		lua.pop();
+
+		lua.get_field(index, lua_string!("policy"));
+		if lua.lua_type(-1) == LUA_TTABLE {
+			self.policy.apply(lua, lua.get_top());
+		}
+		lua.pop();
+
+		lua.get_field(index, lua_string!("scan"));
+		if lua.lua_type(-1) == LUA_TTABLE {
+			self.scan.apply(lua, lua.get_top());
+		}
+		lua.pop();
+
+		if let Some(hold_lua_updates) = get_bool(lua, index, lua_string!("hold_lua_updates")) {
+			self.hold_lua_updates = hold_lua_updates;
+		}
+
+		lua.get_field(index, lua_string!("mirror"));
+		if lua.lua_type(-1) == LUA_TTABLE {
+			self.mirror.apply(lua, lua.get_top());
+		}
+		lua.pop();
+
+		lua.get_field(index, lua_string!("watch"));
+		if lua.lua_type(-1) == LUA_TTABLE {
+			self.watch.apply(lua, lua.get_top());
+		}
+		lua.pop();
+
+		lua.get_field(index, lua_string!("file_info"));
+		if lua.lua_type(-1) == LUA_TTABLE {
+			self.file_info.apply(lua, lua.get_top());
+		}
+		lua.pop();
+	}
+
+	pub unsafe fn push(&self, lua: gmod::lua::State) {
+		lua.new_table();
+
+		lua.push_boolean(self.addworkshop);
+		lua.set_field(-2, lua_string!("addworkshop"));
+
+		self.retry.push(lua);
+		lua.set_field(-2, lua_string!("retry"));
+
+		lua.push_integer(self.concurrency as _);
+		lua.set_field(-2, lua_string!("concurrency"));
+
+		self.quota.push(lua);
+		lua.set_field(-2, lua_string!("quota"));
+
+		self.preflight.push(lua);
+		lua.set_field(-2, lua_string!("preflight"));
+
+		self.policy.push(lua);
+		lua.set_field(-2, lua_string!("policy"));
+
+		self.scan.push(lua);
+		lua.set_field(-2, lua_string!("scan"));
+
+		lua.push_boolean(self.hold_lua_updates);
+		lua.set_field(-2, lua_string!("hold_lua_updates"));
+
+		self.mirror.push(lua);
+		lua.set_field(-2, lua_string!("mirror"));
+
+		self.watch.push(lua);
+		lua.set_field(-2, lua_string!("watch"));
+
+		self.file_info.push(lua);
+		lua.set_field(-2, lua_string!("file_info"));
+	}
+}
+
+pub fn get() -> Config {
+	CONFIG.with(|config| config.borrow().clone())
+}
+
+pub unsafe fn set(lua: gmod::lua::State, index: i32) {
+	CONFIG.with(|config| config.borrow_mut().apply(lua, index));
+}
+
+pub fn reset() {
+	CONFIG.with(|config| *config.borrow_mut() = Config::default());
+}
+
+/// Loads `garrysmod/data/gmsv_workshop/config.json`, if it exists
+pub unsafe fn load(lua: gmod::lua::State) {
+	lua.get_global(lua_string!("util"));
+	lua.get_field(-1, lua_string!("JSONToTable"));
+	lua.get_global(lua_string!("file"));
+	lua.get_field(-1, lua_string!("Read"));
+	lua.remove(-2);
+	lua.push_string(CONFIG_PATH);
+	lua.push_string("DATA");
+
+	if !lua.pcall_ignore(2, 1) {
+		lua.pop_n(2);
+		return;
+	}
+
+	if lua.lua_type(-1) != LUA_TSTRING {
+		// No config file
+		lua.pop_n(3);
+		return;
+	}
+
+	if !lua.pcall_ignore(1, 1) {
+		lua.pop();
+		return;
+	}
+
+	if lua.lua_type(-1) == LUA_TTABLE {
+		set(lua, lua.get_top());
+		println!("[gmsv_workshop] Loaded data/{}", CONFIG_PATH);
+	} else {
+		eprintln!("[gmsv_workshop] data/{} is not a valid JSON object", CONFIG_PATH);
+	}
+
+	lua.pop_n(2);
+}
+
+/// Turns a setting in seconds into a `Duration`, clamped between 0 and `MAX_SECONDS`
+pub fn seconds(secs: f64) -> Duration {
+	if secs.is_nan() {
+		return Duration::ZERO;
+	}
+	Duration::from_secs_f64(secs.clamp(0., MAX_SECONDS))
+}
+
+pub unsafe fn get_bool(lua: gmod::lua::State, index: i32, key: LuaString) -> Option<bool> {
+	lua.get_field(index, key);
+	let value = if lua.lua_type(-1) == LUA_TBOOLEAN { Some(lua.get_boolean(-1)) } else { None };
+	lua.pop();
+	value
+}
+
+pub unsafe fn get_number(lua: gmod::lua::State, index: i32, key: LuaString) -> Option<f64> {
+	lua.get_field(index, key);
+	let value = if lua.lua_type(-1) == LUA_TNUMBER { Some(lua.to_number(-1)) } else { None };
+	lua.pop();
+	value
+}
+
+pub unsafe fn get_string(lua: gmod::lua::State, index: i32, key: LuaString) -> Option<String> {
+	lua.get_field(index, key);
+	let value = if matches!(lua.lua_type(-1), LUA_TSTRING | LUA_TNUMBER) {
+		lua.get_string(-1).map(|str| str.into_owned())
+	} else {
+		None
+	};
+	lua.pop();
+	value
+}
+
+/// Reads an array of strings (or numbers, which are converted to strings)
+pub unsafe fn get_string_list(lua: gmod::lua::State, index: i32, key: LuaString) -> Option<Vec<String>> {
+	lua.get_field(index, key);
+	let value = if lua.lua_type(-1) == LUA_TTABLE {
+		let mut list = Vec::new();
+		for i in 1..=lua.len(-1) {
+			lua.raw_geti(-1, i as _);
+			if matches!(lua.lua_type(-1), LUA_TSTRING | LUA_TNUMBER) {
+				if let Some(str) = lua.get_string(-1) {
+					list.push(str.into_owned());
+				}
+			}
+			lua.pop();
+		}
+		Some(list)
+	} else {
+		None
+	};
+	lua.pop();
+	value
+}
+
+pub unsafe fn push_string_list<I: Iterator<Item = String>>(lua: gmod::lua::State, list: I) {
+	lua.new_table();
+	for (i, str) in list.enumerate() {
+		lua.push_string(&str);
+		lua.raw_seti(-2, (i + 1) as _);
+	}
+}
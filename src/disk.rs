@@ -0,0 +1,63 @@
+use std::path::Path;
+
+/// Bytes available to us on the volume containing `path`
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> std::io::Result<u64> {
+	use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+	let path = CString::new(path.as_os_str().as_bytes()).map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+
+	unsafe {
+		let mut stat: libc::statvfs = std::mem::zeroed();
+		if libc::statvfs(path.as_ptr(), &mut stat) != 0 {
+			return Err(std::io::Error::last_os_error());
+		}
+		// These are narrower on 32-bit targets
+		#[allow(clippy::unnecessary_cast)]
+		Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+	}
+}
+
+/// Bytes available to us on the volume containing `path`
+#[cfg(windows)]
+pub fn free_space(path: &Path) -> std::io::Result<u64> {
+	use std::os::windows::ffi::OsStrExt;
+
+	#[link(name = "kernel32")]
+	extern "system" {
+		fn GetDiskFreeSpaceExW(directory: *const u16, free_bytes_available: *mut u64, total_bytes: *mut u64, total_free_bytes: *mut u64) -> i32;
+	}
+
+	let path = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect::<Vec<u16>>();
+
+	unsafe {
+		let mut available = 0u64;
+		if GetDiskFreeSpaceExW(path.as_ptr(), &mut available, std::ptr::null_mut(), std::ptr::null_mut()) == 0 {
+			return Err(std::io::Error::last_os_error());
+		}
+		Ok(available)
+	}
+}
+
+/// Walks up from `path` until we find something that exists, so we can ask about a directory that hasn't been created yet
+pub fn existing_ancestor(path: &Path) -> &Path {
+	path.ancestors().find(|path| path.exists()).unwrap_or_else(|| Path::new("."))
+}
+
+#[cfg(unix)]
+pub fn same_volume(a: &Path, b: &Path) -> bool {
+	use std::os::unix::fs::MetadataExt;
+	match (a.metadata(), b.metadata()) {
+		(Ok(a), Ok(b)) => a.dev() == b.dev(),
+		_ => false
+	}
+}
+
+#[cfg(windows)]
+pub fn same_volume(a: &Path, b: &Path) -> bool {
+	use std::path::Component;
+	match (a.canonicalize(), b.canonicalize()) {
+		(Ok(a), Ok(b)) => matches!((a.components().next(), b.components().next()), (Some(Component::Prefix(a)), Some(Component::Prefix(b))) if a == b),
+		_ => false
+	}
+}
@@ -0,0 +1,465 @@
+//! Reader and writer for the GMA addon format
+//!
+//! ```text
+//! "GMAD" u8:version u64:steamid u64:timestamp
+//! [version > 1] cstr:required_content... cstr:""
+//! cstr:name cstr:description cstr:author i32:addon_version
+//! { u32:index (0 terminates) cstr:path i64:size u32:crc }...
+//! file contents, in the same order as the entries
+//! [u32:crc of everything above]
+//! ```
+
+use std::{
+	fs::File,
+	io::{BufRead, BufReader, Read, Seek, SeekFrom},
+	path::{Component, Path, PathBuf},
+};
+
+pub const IDENT: &[u8; 4] = b"GMAD";
+pub const VERSION: u8 = 3;
+
+/// GMA paths from Lua are relative to this, like the `GAME` search path
+const GAME_ROOT: &str = "garrysmod";
+/// Files can only be extracted in here
+const DATA_ROOT: &str = "garrysmod/data";
+/// Extensions Lua can read and write in `garrysmod/data`, anything else gets `.txt` appended when extracted
+const DATA_EXTENSIONS: &[&str] = &["txt", "dat", "json", "xml", "csv", "dem", "vcd", "vtf", "png", "jpg", "jpeg", "mp3", "wav", "ogg"];
+/// Directories under `garrysmod/` that addons can ship content in, and that GMAs can be built from
+const CONTENT_DIRS: &[&str] = &["data", "data_static", "gamemodes", "lua", "maps", "materials", "models", "particles", "resource", "scenes", "scripts", "sound"];
+
+#[derive(Clone, Debug)]
+pub struct GmaEntry {
+	pub path: String,
+	pub size: u64,
+	pub crc: u32,
+	/// Offset of the file's contents from the start of the GMA
+	pub offset: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Gma {
+	pub path: PathBuf,
+	pub entries: Vec<GmaEntry>,
+}
+impl Gma {
+	/// Parses the header and file table, leaving the file contents on disk
+	pub fn open<P: Into<PathBuf>>(path: P) -> Result<Gma, std::io::Error> {
+		let path = path.into();
+		let mut r = BufReader::new(File::open(&path)?);
+
+		let mut ident = [0u8; 4];
+		r.read_exact(&mut ident)?;
+		if &ident != IDENT {
+			return Err(invalid_data("not a GMA"));
+		}
+
+		let version = read_u8(&mut r)?;
+		if version > VERSION {
+			return Err(invalid_data("unsupported GMA version"));
+		}
+
+		let _steamid = read_u64(&mut r)?;
+		let _timestamp = read_u64(&mut r)?;
+
+		if version > 1 {
+			// Required content, unused by the game
+			while !read_cstr(&mut r)?.is_empty() {}
+		}
+
+		let _name = read_cstr(&mut r)?;
+		let _description = read_cstr(&mut r)?;
+		let _author = read_cstr(&mut r)?;
+		let _addon_version = read_u32(&mut r)?;
+
+		let mut entries = Vec::new();
+		let mut offset = 0u64;
+		while read_u32(&mut r)? != 0 {
+			let path = read_cstr(&mut r)?;
+			let size = read_u64(&mut r)?;
+			let crc = read_u32(&mut r)?;
+
+			entries.push(GmaEntry { path, size, crc, offset });
+			offset = offset.checked_add(size).ok_or_else(|| invalid_data("GMA file table is corrupt"))?;
+		}
+
+		let data_start = r.stream_position()?;
+		for entry in entries.iter_mut() {
+			entry.offset += data_start;
+		}
+
+		Ok(Gma { path, entries })
+	}
+
+	pub fn entry(&self, path: &str) -> Option<&GmaEntry> {
+		let path = normalize(path);
+		self.entries.iter().find(|entry| normalize(&entry.path).eq_ignore_ascii_case(&path))
+	}
+
+	/// Reads a file's contents and checks them against the CRC in the file table
+	pub fn read(&self, entry: &GmaEntry) -> Result<Vec<u8>, std::io::Error> {
+		let mut f = File::open(&self.path)?;
+		f.seek(SeekFrom::Start(entry.offset))?;
+
+		let mut data = Vec::with_capacity(entry.size.min(64 * 1024 * 1024) as usize);
+		f.take(entry.size).read_to_end(&mut data)?;
+		if data.len() as u64 != entry.size {
+			return Err(std::io::ErrorKind::UnexpectedEof.into());
+		}
+
+		if entry.crc != 0 && crc32fast::hash(&data) != entry.crc {
+			return Err(invalid_data("CRC mismatch"));
+		}
+
+		Ok(data)
+	}
+}
+
+/// Files that changed between two versions of a GMA
+#[derive(Clone, Debug, Default)]
+pub struct GmaDiff {
+	pub added: Vec<String>,
+	pub removed: Vec<String>,
+	/// Files whose size or CRC changed
+	pub modified: Vec<String>,
+}
+impl GmaDiff {
+	pub fn new(old: &Gma, new: &Gma) -> GmaDiff {
+		let table = |gma: &Gma| {
+			gma.entries
+				.iter()
+				.map(|entry| (normalize(&entry.path).to_ascii_lowercase(), (entry.size, entry.crc)))
+				.collect::<std::collections::BTreeMap<_, _>>()
+		};
+		let (old, new) = (table(old), table(new));
+
+		let mut diff = GmaDiff::default();
+		for (path, file) in new.iter() {
+			match old.get(path) {
+				None => diff.added.push(path.clone()),
+				Some(old_file) if old_file != file => diff.modified.push(path.clone()),
+				Some(_) => {}
+			}
+		}
+		diff.removed.extend(old.keys().filter(|path| !new.contains_key(*path)).cloned());
+		diff
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+	}
+
+	/// Whether any Lua file was added, removed or modified
+	pub fn lua_changed(&self) -> bool {
+		self.added.iter().chain(self.removed.iter()).chain(self.modified.iter()).any(|path| path.ends_with(".lua"))
+	}
+
+	/// Pushes `{ added = { string, ... }, removed = { string, ... }, modified = { string, ... }, lua = bool }`
+	pub unsafe fn push(&self, lua: gmod::lua::State) {
+		lua.create_table(0, 4);
+
+		crate::config::push_string_list(lua, self.added.iter().cloned());
+		lua.set_field(-2, lua_string!("added"));
+
+		crate::config::push_string_list(lua, self.removed.iter().cloned());
+		lua.set_field(-2, lua_string!("removed"));
+
+		crate::config::push_string_list(lua, self.modified.iter().cloned());
+		lua.set_field(-2, lua_string!("modified"));
+
+		lua.push_boolean(self.lua_changed());
+		lua.set_field(-2, lua_string!("lua"));
+	}
+}
+
+/// Builds GMA v3 files
+#[derive(Clone, Debug, Default)]
+pub struct GmaWriter {
+	pub steamid: u64,
+	pub name: String,
+	pub description: String,
+	pub author: String,
+	pub addon_version: i32,
+	files: Vec<(String, Vec<u8>)>,
+}
+impl GmaWriter {
+	/// Adds a file, replacing any earlier file with the same path
+	pub fn add(&mut self, path: &str, data: Vec<u8>) -> Result<(), std::io::Error> {
+		let path = sanitize(path)
+			.map(|path| normalize(&path.to_string_lossy()).to_ascii_lowercase())
+			.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid path in GMA"))?;
+
+		self.files.retain(|(existing, _)| *existing != path);
+		self.files.push((path, data));
+		Ok(())
+	}
+
+	pub fn write(&self) -> Vec<u8> {
+		let mut gma = Vec::with_capacity(self.files.iter().map(|(path, data)| path.len() + data.len() + 17).sum::<usize>() + 256);
+
+		gma.extend_from_slice(IDENT);
+		gma.push(VERSION);
+		gma.extend_from_slice(&self.steamid.to_le_bytes());
+		gma.extend_from_slice(&std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0).to_le_bytes());
+
+		// No required content
+		gma.push(0);
+
+		for str in [&self.name, &self.description, &self.author] {
+			write_cstr(&mut gma, str);
+		}
+		gma.extend_from_slice(&self.addon_version.to_le_bytes());
+
+		for (i, (path, data)) in self.files.iter().enumerate() {
+			gma.extend_from_slice(&(i as u32 + 1).to_le_bytes());
+			write_cstr(&mut gma, path);
+			gma.extend_from_slice(&(data.len() as i64).to_le_bytes());
+			gma.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+		}
+		gma.extend_from_slice(&0u32.to_le_bytes());
+
+		for (_, data) in self.files.iter() {
+			gma.extend_from_slice(data);
+		}
+
+		let crc = crc32fast::hash(&gma);
+		gma.extend_from_slice(&crc.to_le_bytes());
+
+		gma
+	}
+}
+
+/// Writes a GMA to `dest`, relative to `garrysmod/data`, LZMA compressing it like `util.Compress` if asked to
+pub fn create(writer: &GmaWriter, dest: &str, compress: bool) -> Result<PathBuf, std::io::Error> {
+	let dest = sanitize(dest).ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid destination path"))?;
+
+	let mut data = writer.write();
+	if compress {
+		data = gmod_lzma::compress(&data, 5).map_err(|_| std::io::Error::other("LZMA compression failed"))?;
+	}
+
+	let path = Path::new(DATA_ROOT).join(&dest);
+	if let Some(parent) = path.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+	std::fs::write(path, data)?;
+
+	Ok(dest)
+}
+
+/// Reads a file from a path relative to `garrysmod/`, which must be in one of the content directories
+pub fn read_game_file(path: &str) -> Result<Vec<u8>, std::io::Error> {
+	let path = sanitize(path).ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid file path"))?;
+	if !is_content_path(&path) {
+		return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "file is outside the content directories"));
+	}
+	std::fs::read(Path::new(GAME_ROOT).join(path))
+}
+
+/// Whether a sanitized path is inside one of the content directories, so `cfg/` and the like can't be read
+fn is_content_path(path: &Path) -> bool {
+	match path.components().next() {
+		Some(Component::Normal(dir)) if path.components().nth(1).is_some() => {
+			dir.to_str().map(|dir| CONTENT_DIRS.iter().any(|content| dir.eq_ignore_ascii_case(content))).unwrap_or(false)
+		},
+		_ => false
+	}
+}
+
+/// Forward-slashed form of a path inside a GMA, without a leading slash
+pub fn normalize(path: &str) -> String {
+	path.replace('\\', "/").trim_start_matches('/').to_owned()
+}
+
+/// Turns a path from Lua into a relative path that can't escape the directory it is joined onto
+pub fn sanitize(path: &str) -> Option<PathBuf> {
+	let mut sanitized = PathBuf::new();
+	for component in Path::new(&path.replace('\\', "/")).components() {
+		match component {
+			// Drive letters and alternate data streams are only prefixes on Windows, refuse them everywhere
+			Component::Normal(component) if component.to_string_lossy().contains(':') => return None,
+			Component::Normal(component) => sanitized.push(component),
+			Component::CurDir => {},
+			Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None
+		}
+	}
+	if sanitized.as_os_str().is_empty() {
+		None
+	} else {
+		Some(sanitized)
+	}
+}
+
+pub fn open_cached(workshop_id: steamworks::PublishedFileId) -> Result<Gma, std::io::Error> {
+	Gma::open(Path::new("garrysmod/cache/srcds").join(format!("{}.gma", workshop_id)))
+}
+
+/// Opens a GMA from a path relative to `garrysmod/`, such as the one `steamworks.DownloadUGC` returns
+pub fn open_game_path(path: &str) -> Result<Gma, std::io::Error> {
+	let path = sanitize(path).ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid GMA path"))?;
+	Gma::open(Path::new(GAME_ROOT).join(path))
+}
+
+/// Reads a single file out of a GMA
+pub fn read_file(gma: &Gma, inner_path: &str) -> Result<Vec<u8>, std::io::Error> {
+	let entry = gma.entry(inner_path).ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "file not found in GMA"))?;
+	gma.read(entry)
+}
+
+/// Appends `.txt` to paths whose extension isn't allowed in `garrysmod/data`, like the game does
+fn data_path(path: PathBuf) -> PathBuf {
+	let allowed = path
+		.extension()
+		.and_then(|ext| ext.to_str())
+		.map(|ext| DATA_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+		.unwrap_or(false);
+
+	if allowed {
+		path
+	} else {
+		let mut path = path.into_os_string();
+		path.push(".txt");
+		path.into()
+	}
+}
+
+/// Extracts a single file out of a GMA to `dest`, relative to `garrysmod/data`
+///
+/// Returns the path it was written to, which has `.txt` appended if the data folder doesn't allow its extension.
+pub fn extract(gma: &Gma, inner_path: &str, dest: &str) -> Result<PathBuf, std::io::Error> {
+	let dest = sanitize(dest).ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid destination path"))?;
+	let dest = data_path(dest);
+	let data = read_file(gma, inner_path)?;
+
+	let path = Path::new(DATA_ROOT).join(&dest);
+	if let Some(parent) = path.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+	std::fs::write(path, data)?;
+
+	Ok(dest)
+}
+
+fn invalid_data(msg: &'static str) -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+fn read_u8<R: Read>(r: &mut R) -> Result<u8, std::io::Error> {
+	let mut buf = [0u8; 1];
+	r.read_exact(&mut buf)?;
+	Ok(buf[0])
+}
+
+fn read_u32<R: Read>(r: &mut R) -> Result<u32, std::io::Error> {
+	let mut buf = [0u8; 4];
+	r.read_exact(&mut buf)?;
+	Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> Result<u64, std::io::Error> {
+	let mut buf = [0u8; 8];
+	r.read_exact(&mut buf)?;
+	Ok(u64::from_le_bytes(buf))
+}
+
+fn write_cstr(buf: &mut Vec<u8>, str: &str) {
+	buf.extend(str.bytes().filter(|byte| *byte != 0));
+	buf.push(0);
+}
+
+fn read_cstr<R: BufRead>(r: &mut R) -> Result<String, std::io::Error> {
+	let mut buf = Vec::new();
+	r.read_until(0, &mut buf)?;
+	if buf.pop() != Some(0) {
+		return Err(std::io::ErrorKind::UnexpectedEof.into());
+	}
+	Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn data_paths() {
+		assert_eq!(data_path(PathBuf::from("a/b.txt")), PathBuf::from("a/b.txt"));
+		assert_eq!(data_path(PathBuf::from("a/b.JSON")), PathBuf::from("a/b.JSON"));
+		assert_eq!(data_path(PathBuf::from("a/init.lua")), PathBuf::from("a/init.lua.txt"));
+		assert_eq!(data_path(PathBuf::from("a/b.vmt")), PathBuf::from("a/b.vmt.txt"));
+		assert_eq!(data_path(PathBuf::from("a/readme")), PathBuf::from("a/readme.txt"));
+	}
+
+	#[test]
+	fn sanitize_paths() {
+		assert_eq!(sanitize("lua/autorun/init.lua"), Some(PathBuf::from("lua/autorun/init.lua")));
+		assert_eq!(sanitize("lua\\autorun\\init.lua"), Some(PathBuf::from("lua/autorun/init.lua")));
+		assert_eq!(sanitize("./lua/./init.lua"), Some(PathBuf::from("lua/init.lua")));
+
+		assert_eq!(sanitize(""), None);
+		assert_eq!(sanitize("."), None);
+		assert_eq!(sanitize(".."), None);
+		assert_eq!(sanitize("../server.cfg"), None);
+		assert_eq!(sanitize("lua/../../server.cfg"), None);
+		assert_eq!(sanitize("lua\\..\\..\\server.cfg"), None);
+		assert_eq!(sanitize("/etc/passwd"), None);
+		assert_eq!(sanitize("\\etc\\passwd"), None);
+		assert_eq!(sanitize("\\\\server\\share\\file"), None);
+		assert_eq!(sanitize("C:\\Windows\\win.ini"), None);
+		assert_eq!(sanitize("C:win.ini"), None);
+		assert_eq!(sanitize("lua/init.lua:stream"), None);
+	}
+
+	#[test]
+	fn content_paths() {
+		let content = |path: &str| is_content_path(&sanitize(path).unwrap());
+		assert!(content("lua/autorun/init.lua"));
+		assert!(content("Materials/x.vmt"));
+		assert!(content("data/saves/1.txt"));
+		assert!(!content("cfg/server.cfg"));
+		assert!(!content("lua"));
+		assert!(!content("gameinfo.txt"));
+		assert!(!content("addons/x/lua/init.lua"));
+	}
+
+	#[test]
+	fn round_trip() {
+		let mut writer = GmaWriter {
+			steamid: 76561197960287930,
+			name: "Test".to_owned(),
+			description: "Round trip".to_owned(),
+			author: "gmsv_workshop".to_owned(),
+			addon_version: 1,
+			..Default::default()
+		};
+		writer.add("lua/autorun/init.lua", b"print(\"hello\")".to_vec()).unwrap();
+		writer.add("materials\\test.vmt", b"\"LightmappedGeneric\" {}".to_vec()).unwrap();
+		writer.add("data/empty.txt", Vec::new()).unwrap();
+		writer.add("LUA/AUTORUN/INIT.LUA", b"print(\"replaced\")".to_vec()).unwrap();
+		assert!(writer.add("../server.cfg", Vec::new()).is_err());
+
+		let data = writer.write();
+		let (body, crc) = data.split_at(data.len() - 4);
+		assert_eq!(crc32fast::hash(body).to_le_bytes(), crc);
+
+		let path = std::env::temp_dir().join(format!("gmsv_workshop_round_trip_{}.gma", std::process::id()));
+		std::fs::write(&path, &data).unwrap();
+
+		let gma = Gma::open(&path).unwrap();
+		assert_eq!(gma.entries.len(), 3);
+		assert_eq!(read_file(&gma, "materials/test.vmt").unwrap(), b"\"LightmappedGeneric\" {}");
+		assert_eq!(read_file(&gma, "/Data\\Empty.txt").unwrap(), b"");
+		assert_eq!(read_file(&gma, "lua/autorun/init.lua").unwrap(), b"print(\"replaced\")");
+		assert!(read_file(&gma, "lua/missing.lua").is_err());
+
+		// Flip a byte of the first file's contents, the CRC in the file table should catch it
+		let mut corrupt = data.clone();
+		let offset = gma.entries.iter().find(|entry| entry.size != 0).unwrap().offset as usize;
+		corrupt[offset] ^= 0xFF;
+		std::fs::write(&path, &corrupt).unwrap();
+
+		let gma = Gma::open(&path).unwrap();
+		let entry = gma.entries.iter().find(|entry| entry.size != 0).unwrap();
+		assert_eq!(gma.read(entry).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+		std::fs::remove_file(&path).unwrap();
+	}
+}
@@ -0,0 +1,9 @@
+/// Calls `hook.Run(name, ...)`, where `args` pushes the arguments and returns how many it pushed
+pub unsafe fn run<F: FnOnce(gmod::lua::State) -> i32>(lua: gmod::lua::State, name: &str, args: F) {
+	lua.get_global(lua_string!("hook"));
+	lua.get_field(-1, lua_string!("Run"));
+	lua.push_string(name);
+	let nargs = args(lua);
+	lua.pcall_ignore(nargs + 1, 0);
+	lua.pop();
+}
@@ -0,0 +1,99 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageType {
+	Jpeg,
+	Png,
+	Gif,
+}
+impl ImageType {
+	pub const ALL: [ImageType; 3] = [ImageType::Jpeg, ImageType::Png, ImageType::Gif];
+
+	/// Identifies an image from its magic bytes
+	pub fn detect(data: &[u8]) -> Option<ImageType> {
+		if data.starts_with(b"\xFF\xD8\xFF") {
+			Some(ImageType::Jpeg)
+		} else if data.starts_with(b"\x89PNG\r\n\x1A\n") {
+			Some(ImageType::Png)
+		} else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+			Some(ImageType::Gif)
+		} else {
+			None
+		}
+	}
+
+	pub fn extension(self) -> &'static str {
+		match self {
+			ImageType::Jpeg => "jpg",
+			ImageType::Png => "png",
+			ImageType::Gif => "gif",
+		}
+	}
+
+	/// Width and height of the image, read from its header
+	pub fn dimensions(self, data: &[u8]) -> Option<(u32, u32)> {
+		match self {
+			// IHDR is always the first chunk
+			ImageType::Png => {
+				let ihdr = data.get(12..24)?;
+				if &ihdr[..4] != b"IHDR" {
+					return None;
+				}
+				Some((
+					u32::from_be_bytes(ihdr[4..8].try_into().unwrap()),
+					u32::from_be_bytes(ihdr[8..12].try_into().unwrap()),
+				))
+			},
+
+			// Logical screen descriptor
+			ImageType::Gif => {
+				let screen = data.get(6..10)?;
+				Some((
+					u16::from_le_bytes([screen[0], screen[1]]) as u32,
+					u16::from_le_bytes([screen[2], screen[3]]) as u32,
+				))
+			},
+
+			ImageType::Jpeg => jpeg_dimensions(data),
+		}
+	}
+}
+
+/// Walks the JPEG markers until the start of frame
+fn jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+	let mut pos = 2;
+	loop {
+		// Markers can be padded with any number of 0xFF
+		while *data.get(pos)? == 0xFF && *data.get(pos + 1)? == 0xFF {
+			pos += 1;
+		}
+		if *data.get(pos)? != 0xFF {
+			return None;
+		}
+
+		let marker = *data.get(pos + 1)?;
+		pos += 2;
+
+		match marker {
+			// Standalone markers
+			0x01 | 0xD0..=0xD7 => continue,
+
+			// End of image or start of scan before any frame
+			0xD9 | 0xDA => return None,
+
+			// SOF0-SOF15, except DHT, JPG and DAC which share the range
+			0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+				let frame = data.get(pos..pos + 7)?;
+				let height = u16::from_be_bytes([frame[3], frame[4]]) as u32;
+				let width = u16::from_be_bytes([frame[5], frame[6]]) as u32;
+				return Some((width, height));
+			},
+
+			_ => {
+				let len = u16::from_be_bytes([*data.get(pos)?, *data.get(pos + 1)?]) as usize;
+				if len < 2 {
+					return None;
+				}
+				pos += len;
+			}
+		}
+	}
+}
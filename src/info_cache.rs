@@ -0,0 +1,116 @@
+use std::{
+	collections::HashMap,
+	time::{Duration, Instant},
+};
+use steamworks::PublishedFileId;
+
+/// An item's details, children and required apps, or a `steamworks.FileInfo` error code
+///
+/// The required apps are `None` if Steam couldn't list them.
+pub type FileInfo = Result<(steamworks::QueryResult, Vec<PublishedFileId>, Option<steamworks::AppDependencies>), i32>;
+
+#[derive(Clone, Debug)]
+pub struct InfoCacheConfig {
+	/// Seconds to reuse a `steamworks.FileInfo` result for, 0 to disable the cache
+	pub ttl: f64,
+	/// Seconds to reuse a failed `steamworks.FileInfo` result for
+	pub error_ttl: f64,
+	/// Language to ask Steam for titles and descriptions in, unless a call says otherwise
+	pub language: Option<String>,
+	/// Most results to keep, 0 for no limit
+	pub max_entries: usize,
+}
+impl Default for InfoCacheConfig {
+	fn default() -> Self {
+		InfoCacheConfig { ttl: 60., error_ttl: 10., language: None, max_entries: 4096 }
+	}
+}
+impl InfoCacheConfig {
+	/// Overrides any known keys with the values in the Lua table at `index`
+	pub unsafe fn apply(&mut self, lua: gmod::lua::State, index: i32) {
+		if let Some(ttl) = crate::config::get_number(lua, index, lua_string!("ttl")) {
+			self.ttl = ttl.max(0.);
+		}
+		if let Some(error_ttl) = crate::config::get_number(lua, index, lua_string!("error_ttl")) {
+			self.error_ttl = error_ttl.max(0.);
+		}
+		if let Some(language) = crate::config::get_string(lua, index, lua_string!("language")) {
+			self.language = Some(language.to_ascii_lowercase()).filter(|language| !language.is_empty());
+		}
+		if let Some(max_entries) = crate::config::get_number(lua, index, lua_string!("max_entries")) {
+			self.max_entries = max_entries.max(0.) as usize;
+		}
+	}
+
+	pub unsafe fn push(&self, lua: gmod::lua::State) {
+		lua.new_table();
+
+		lua.push_number(self.ttl);
+		lua.set_field(-2, lua_string!("ttl"));
+
+		lua.push_number(self.error_ttl);
+		lua.set_field(-2, lua_string!("error_ttl"));
+
+		if let Some(language) = &self.language {
+			lua.push_string(language);
+			lua.set_field(-2, lua_string!("language"));
+		}
+
+		lua.push_integer(self.max_entries as _);
+		lua.set_field(-2, lua_string!("max_entries"));
+	}
+
+	fn ttl(&self, info: &FileInfo) -> Duration {
+		crate::config::seconds(if info.is_ok() { self.ttl } else { self.error_ttl })
+	}
+}
+
+/// An item, and the language its details were requested in
+pub type InfoKey = (PublishedFileId, Option<String>);
+
+#[derive(Debug, Default)]
+pub struct InfoCache(HashMap<InfoKey, (Instant, FileInfo)>);
+impl InfoCache {
+	pub fn get(&mut self, key: &InfoKey, config: &InfoCacheConfig) -> Option<FileInfo> {
+		let (fetched, info) = self.0.get(key)?;
+
+		if fetched.elapsed() < config.ttl(info) {
+			return Some(info.clone());
+		}
+
+		self.0.remove(key);
+		None
+	}
+
+	/// Caches a result, making room by dropping expired results and then the oldest ones if the cache is full
+	pub fn insert(&mut self, key: InfoKey, info: FileInfo, config: &InfoCacheConfig) {
+		if config.max_entries != 0 && self.0.len() >= config.max_entries && !self.0.contains_key(&key) {
+			self.0.retain(|_, (fetched, info)| fetched.elapsed() < config.ttl(info));
+
+			while self.0.len() >= config.max_entries {
+				let oldest = match self.0.iter().min_by_key(|(_, (fetched, _))| *fetched) {
+					Some((oldest, _)) => oldest.clone(),
+					None => break
+				};
+				self.0.remove(&oldest);
+			}
+		}
+
+		self.0.insert(key, (Instant::now(), info));
+	}
+
+	/// Finds the required apps in a fresh result for the item in any language, since they don't depend on it
+	pub fn app_dependencies(&self, workshop_id: PublishedFileId, config: &InfoCacheConfig) -> Option<steamworks::AppDependencies> {
+		self.0.iter().find_map(|((cached, _), (fetched, info))| match info {
+			Ok((_, _, Some(required_apps))) if *cached == workshop_id && fetched.elapsed() < config.ttl(info) => Some(required_apps.clone()),
+			_ => None
+		})
+	}
+
+	/// Forgets the item in every language
+	pub fn invalidate(&mut self, workshop_id: PublishedFileId) -> bool {
+		let len = self.0.len();
+		self.0.retain(|(cached, _), _| *cached != workshop_id);
+		self.0.len() != len
+	}
+}
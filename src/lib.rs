@@ -9,7 +9,22 @@
 #[macro_use] extern crate gmod;
 
 mod workshop;
+mod bbcode;
 mod callbacks;
+mod config;
+mod disk;
+mod gma;
+mod hooks;
+mod image;
+mod info_cache;
+mod mirror;
+mod mounts;
+mod policy;
+mod preflight;
+mod quota;
+mod retry;
+mod scan;
+mod watch;
 
 use std::cell::Cell;
 
@@ -43,22 +58,33 @@ unsafe extern "C-unwind" fn download(lua: gmod::lua::State) -> i32 {
 
 	lua.check_function(2);
 
-	let callback = if !lua.is_nil(2) {
+	let callbacks = if !lua.is_nil(2) {
 		lua.check_function(2);
 		lua.push_value(2);
-		Some(lua.reference())
+		vec![lua.reference()]
 	} else {
-		None
+		Vec::new()
+	};
+
+	let options = if lua.get_top() >= 3 && !lua.is_nil(3) {
+		lua.check_table(3);
+		workshop::downloads::DownloadOptions::from_lua(lua, 3)
+	} else {
+		Default::default()
 	};
 
 	STEAM.with(|steam| {
-		steam.download(steamworks::PublishedFileId(workshop_id as _), callback);
+		steam.download(steamworks::PublishedFileId(workshop_id as _), workshop::downloads::DownloadRequest::new(callbacks, options));
 	});
 
 	0
 }
 
 unsafe extern "C-unwind" fn file_info(lua: gmod::lua::State) -> i32 {
+	if lua.is_table(1) {
+		return file_info_batch(lua);
+	}
+
 	let workshop_id = match lua.check_string(1).parse::<u64>() {
 		Ok(workshop_id) => workshop_id,
 		Err(_) => {
@@ -73,24 +99,419 @@ unsafe extern "C-unwind" fn file_info(lua: gmod::lua::State) -> i32 {
 
 	lua.check_function(2);
 
+	let options = if lua.get_top() >= 3 && !lua.is_nil(3) {
+		lua.check_table(3);
+		workshop::query::FileInfoOptions::from_lua(lua, 3)
+	} else {
+		Default::default()
+	};
+
 	let callback = {
 		lua.push_value(2);
 		lua.reference()
 	};
 
 	STEAM.with(|steam| {
-		steam.file_info(steamworks::PublishedFileId(workshop_id), callback);
+		steam.file_info(steamworks::PublishedFileId(workshop_id), callback, options);
+	});
+
+	0
+}
+
+/// `steamworks.FileInfo({ id, ... }, callback[, options])`
+unsafe fn file_info_batch(lua: gmod::lua::State) -> i32 {
+	lua.check_function(2);
+
+	let mut items = Vec::new();
+	for i in 1..=lua.len(1) {
+		lua.raw_geti(1, i as _);
+		let workshop_id = match lua.lua_type(-1) {
+			gmod::lua::LUA_TSTRING | gmod::lua::LUA_TNUMBER => lua.get_string(-1).and_then(|workshop_id| workshop_id.parse::<u64>().ok()),
+			_ => None
+		};
+		match workshop_id {
+			Some(workshop_id) => items.push(steamworks::PublishedFileId(workshop_id)),
+			None => lua.error("bad workshop ID in list")
+		}
+		lua.pop();
+	}
+
+	let options = if lua.get_top() >= 3 && !lua.is_nil(3) {
+		lua.check_table(3);
+		workshop::query::FileInfoOptions::from_lua(lua, 3)
+	} else {
+		Default::default()
+	};
+
+	let callback = {
+		lua.push_value(2);
+		lua.reference()
+	};
+
+	STEAM.with(|steam| {
+		steam.file_info_batch(items, callback, options);
+	});
+
+	0
+}
+
+unsafe extern "C-unwind" fn download_file(lua: gmod::lua::State) -> i32 {
+	let handle = lua.check_string(1).parse::<u64>();
+	let uncompress = lua.get_boolean(2);
+	lua.check_function(3);
+
+	let handle = match handle {
+		Ok(handle) if handle != 0 => handle,
+		_ => {
+			lua.push_value(3);
+			lua.push_nil();
+			lua.pcall_ignore(1, 0);
+			return 0;
+		}
+	};
+
+	let callback = {
+		lua.push_value(3);
+		lua.reference()
+	};
+
+	STEAM.with(|steam| {
+		steam.download_file(steamworks::UGCHandle(handle), workshop::files::FileRequest { uncompress, callback });
 	});
 
 	0
 }
 
+unsafe extern "C-unwind" fn download_preview(lua: gmod::lua::State) -> i32 {
+	let workshop_id = match lua.check_string(1).parse::<u64>() {
+		Ok(workshop_id) => workshop_id,
+		Err(_) => {
+			lua.check_function(2);
+			lua.push_value(2);
+			lua.push_nil();
+			lua.push_string("invalid workshop ID");
+			lua.pcall_ignore(2, 0);
+			return 0;
+		}
+	};
+
+	lua.check_function(2);
+
+	let callback = {
+		lua.push_value(2);
+		lua.reference()
+	};
+
+	STEAM.with(|steam| {
+		steam.download_preview(steamworks::PublishedFileId(workshop_id), callback);
+	});
+
+	0
+}
+
+unsafe extern "C-unwind" fn invalidate_file_info(lua: gmod::lua::State) -> i32 {
+	let workshop_id = match lua.check_string(1).parse::<u64>() {
+		Ok(workshop_id) => workshop_id,
+		Err(_) => {
+			lua.push_boolean(false);
+			return 1;
+		}
+	};
+
+	lua.push_boolean(STEAM.with(|steam| steam.invalidate_file_info(steamworks::PublishedFileId(workshop_id))));
+	1
+}
+
+unsafe extern "C-unwind" fn add_workshop(lua: gmod::lua::State) -> i32 {
+	let workshop_id = match lua.check_string(1).parse::<u64>() {
+		Ok(workshop_id) => workshop_id,
+		Err(_) => return 0
+	};
+
+	STEAM.with(|steam| {
+		steam.add_workshop(steamworks::PublishedFileId(workshop_id));
+	});
+
+	0
+}
+
+unsafe extern "C-unwind" fn get_added_workshop(lua: gmod::lua::State) -> i32 {
+	STEAM.with(|steam| {
+		let registered = steam.addworkshop.borrow();
+		lua.create_table(registered.len() as _, 0);
+		for (i, workshop_id) in registered.iter().enumerate() {
+			lua.push_string(&workshop_id.to_string());
+			lua.raw_seti(-2, (i + 1) as _);
+		}
+	});
+
+	1
+}
+
+unsafe extern "C-unwind" fn attach_download(lua: gmod::lua::State) -> i32 {
+	let workshop_id = match lua.check_string(1).parse::<u64>() {
+		Ok(workshop_id) => workshop_id,
+		Err(_) => {
+			lua.push_boolean(false);
+			return 1;
+		}
+	};
+
+	lua.check_function(2);
+	lua.push_value(2);
+	let callback = lua.reference();
+
+	let attached = STEAM.with(|steam| steam.attach_download(steamworks::PublishedFileId(workshop_id), callback));
+	if !attached {
+		lua.dereference(callback);
+	}
+
+	lua.push_boolean(attached);
+	1
+}
+
+unsafe extern "C-unwind" fn set_download_priority(lua: gmod::lua::State) -> i32 {
+	let workshop_id = match lua.check_string(1).parse::<u64>() {
+		Ok(workshop_id) => workshop_id,
+		Err(_) => {
+			lua.push_boolean(false);
+			return 1;
+		}
+	};
+
+	let priority = match workshop::downloads::DownloadPriority::from_lua(lua, 2) {
+		Some(priority) => priority,
+		None => lua.error("bad argument #2 to 'SetDownloadPriority' (expected \"low\", \"normal\" or \"high\")")
+	};
+
+	lua.push_boolean(STEAM.with(|steam| steam.set_download_priority(steamworks::PublishedFileId(workshop_id), priority)));
+	1
+}
+
+unsafe extern "C-unwind" fn get_pending_downloads(lua: gmod::lua::State) -> i32 {
+	STEAM.with(|steam| {
+		let in_flight = steam.in_flight();
+		lua.create_table(in_flight.len() as _, 0);
+		for (i, workshop_id) in in_flight.into_iter().enumerate() {
+			lua.push_string(&workshop_id.to_string());
+			lua.raw_seti(-2, (i + 1) as _);
+		}
+	});
+
+	1
+}
+
+unsafe extern "C-unwind" fn get_download_quota(lua: gmod::lua::State) -> i32 {
+	let caller = lua.check_string(1).into_owned();
+	let quota = config::get().quota;
+
+	STEAM.with(|steam| {
+		lua.new_table();
+
+		lua.push_integer(steam.caller_in_flight(&caller) as _);
+		lua.set_field(-2, lua_string!("in_flight"));
+
+		lua.push_number(steam.quota_usage.borrow_mut().bytes(&caller, quota.window()) as _);
+		lua.set_field(-2, lua_string!("bytes"));
+	});
+
+	1
+}
+
+unsafe extern "C-unwind" fn get_mount_conflicts(lua: gmod::lua::State) -> i32 {
+	STEAM.with(|steam| mounts::push_conflicts(lua, &steam.mounts.borrow().conflicts()));
+	1
+}
+
+unsafe extern "C-unwind" fn approve_workshop_update(lua: gmod::lua::State) -> i32 {
+	let workshop_id = match lua.check_string(1).parse::<u64>() {
+		Ok(workshop_id) => workshop_id,
+		Err(_) => {
+			lua.push_boolean(false);
+			return 1;
+		}
+	};
+
+	lua.push_boolean(STEAM.with(|steam| steam.approve_update(steamworks::PublishedFileId(workshop_id))));
+	1
+}
+
+unsafe extern "C-unwind" fn get_held_workshop_updates(lua: gmod::lua::State) -> i32 {
+	STEAM.with(|steam| {
+		let held = steam.held_updates();
+		lua.create_table(0, held.len() as _);
+		for (workshop_id, diff) in held {
+			lua.push_string(&workshop_id.to_string());
+			diff.push(lua);
+			lua.set_table(-3);
+		}
+	});
+
+	1
+}
+
+unsafe extern "C-unwind" fn set_config(lua: gmod::lua::State) -> i32 {
+	lua.check_table(1);
+	config::set(lua, 1);
+
+	lua.get_field(1, lua_string!("mirror"));
+	let mirror_changed = !lua.is_nil(-1);
+	lua.pop();
+
+	lua.get_field(1, lua_string!("watch"));
+	let watch_changed = !lua.is_nil(-1);
+	lua.pop();
+
+	STEAM.with(|steam| {
+		if mirror_changed {
+			steam.start_mirror(lua);
+		}
+		if watch_changed {
+			steam.start_watch(lua);
+		}
+	});
+
+	0
+}
+
+unsafe extern "C-unwind" fn get_config(lua: gmod::lua::State) -> i32 {
+	config::get().push(lua);
+	1
+}
+
+unsafe extern "C-unwind" fn gma_extract(lua: gmod::lua::State) -> i32 {
+	let path = lua.check_string(1).into_owned();
+	let inner_path = lua.check_string(2).into_owned();
+	let dest = if lua.get_top() >= 3 && !lua.is_nil(3) {
+		lua.check_string(3).into_owned()
+	} else {
+		format!("gmsv_workshop/extract/{}", gma::normalize(&inner_path))
+	};
+
+	match gma::open_game_path(&path).and_then(|gma| gma::extract(&gma, &inner_path, &dest)) {
+		Ok(dest) => {
+			lua.push_string(&gma::normalize(&dest.to_string_lossy()));
+			1
+		},
+		Err(err) => {
+			lua.push_boolean(false);
+			lua.push_string(&err.to_string());
+			2
+		}
+	}
+}
+
+unsafe extern "C-unwind" fn gma_read(lua: gmod::lua::State) -> i32 {
+	let path = lua.check_string(1).into_owned();
+	let inner_path = lua.check_string(2).into_owned();
+
+	match gma::open_game_path(&path).and_then(|gma| gma::read_file(&gma, &inner_path)) {
+		Ok(data) => {
+			lua.push_binary_string(&data);
+			1
+		},
+		Err(err) => {
+			lua.push_nil();
+			lua.push_string(&err.to_string());
+			2
+		}
+	}
+}
+
+/// Reads one entry of `steamworks.GMACreate`'s file list: either a path relative to `garrysmod/`, or `{ path = string, data = string }` or `{ path = string, source = string }`
+unsafe fn gma_file(lua: gmod::lua::State, index: i32) -> Result<(String, Vec<u8>), std::io::Error> {
+	let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidInput, "files must be paths or { path = string, data = string } tables");
+
+	match lua.lua_type(index) {
+		gmod::lua::LUA_TSTRING => {
+			let path = lua.get_string(index).ok_or_else(invalid)?.into_owned();
+			let data = gma::read_game_file(&path)?;
+			Ok((path, data))
+		},
+
+		gmod::lua::LUA_TTABLE => {
+			let path = config::get_string(lua, index, lua_string!("path")).ok_or_else(invalid)?;
+
+			lua.get_field(index, lua_string!("data"));
+			let data = lua.get_binary_string(-1).map(|data| data.to_vec());
+			lua.pop();
+
+			let data = match data {
+				Some(data) => data,
+				None => gma::read_game_file(&config::get_string(lua, index, lua_string!("source")).ok_or_else(invalid)?)?
+			};
+
+			Ok((path, data))
+		},
+
+		_ => Err(invalid())
+	}
+}
+
+unsafe extern "C-unwind" fn gma_create(lua: gmod::lua::State) -> i32 {
+	let dest = lua.check_string(1).into_owned();
+	lua.check_table(2);
+
+	let mut writer = gma::GmaWriter::default();
+	writer.name = std::path::Path::new(&dest).file_stem().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+	writer.addon_version = 1;
+	let mut compress = false;
+
+	if lua.get_top() >= 3 && !lua.is_nil(3) {
+		lua.check_table(3);
+
+		if let Some(name) = config::get_string(lua, 3, lua_string!("name")) {
+			writer.name = name;
+		}
+		if let Some(description) = config::get_string(lua, 3, lua_string!("description")) {
+			writer.description = description;
+		}
+		if let Some(author) = config::get_string(lua, 3, lua_string!("author")) {
+			writer.author = author;
+		}
+		if let Some(steamid) = config::get_string(lua, 3, lua_string!("steamid")).and_then(|steamid| steamid.parse().ok()) {
+			writer.steamid = steamid;
+		}
+		if let Some(version) = config::get_number(lua, 3, lua_string!("version")) {
+			writer.addon_version = version as i32;
+		}
+		if let Some(compress_) = config::get_bool(lua, 3, lua_string!("compress")) {
+			compress = compress_;
+		}
+	}
+
+	let mut res = Ok(());
+	for i in 1..=lua.len(2) {
+		lua.raw_geti(2, i);
+		res = gma_file(lua, lua.get_top()).and_then(|(path, data)| writer.add(&path, data));
+		lua.pop();
+
+		if res.is_err() {
+			break;
+		}
+	}
+
+	match res.and_then(|_| gma::create(&writer, &dest, compress)) {
+		Ok(dest) => {
+			lua.push_string(&gma::normalize(&dest.to_string_lossy()));
+			1
+		},
+		Err(err) => {
+			lua.push_boolean(false);
+			lua.push_string(&err.to_string());
+			2
+		}
+	}
+}
+
 #[gmod13_open]
 unsafe fn gmod13_open(lua: gmod::lua::State) -> i32 {
 	LUA.with(|cell| {
 		cell.set(Some(lua));
 	});
 
+	config::load(lua);
+
 	lua.get_global(lua_string!("steamworks"));
 	if lua.is_nil(-1) {
 		lua.pop();
@@ -106,7 +527,77 @@ unsafe fn gmod13_open(lua: gmod::lua::State) -> i32 {
 	lua.push_function(file_info);
 	lua.set_field(-2, lua_string!("FileInfo"));
 
+	lua.push_function(download_file);
+	lua.set_field(-2, lua_string!("Download"));
+
+	lua.push_function(download_preview);
+	lua.set_field(-2, lua_string!("DownloadPreview"));
+
+	lua.push_function(invalidate_file_info);
+	lua.set_field(-2, lua_string!("InvalidateFileInfo"));
+
+	lua.push_function(add_workshop);
+	lua.set_field(-2, lua_string!("AddWorkshop"));
+
+	lua.push_function(get_added_workshop);
+	lua.set_field(-2, lua_string!("GetAddedWorkshop"));
+
+	lua.push_function(set_config);
+	lua.set_field(-2, lua_string!("SetWorkshopConfig"));
+
+	lua.push_function(get_config);
+	lua.set_field(-2, lua_string!("GetWorkshopConfig"));
+
+	lua.push_function(attach_download);
+	lua.set_field(-2, lua_string!("AttachDownload"));
+
+	lua.push_function(get_pending_downloads);
+	lua.set_field(-2, lua_string!("GetPendingDownloads"));
+
+	lua.push_function(set_download_priority);
+	lua.set_field(-2, lua_string!("SetDownloadPriority"));
+
+	lua.push_function(get_download_quota);
+	lua.set_field(-2, lua_string!("GetDownloadQuota"));
+
+	lua.push_function(get_mount_conflicts);
+	lua.set_field(-2, lua_string!("GetMountConflicts"));
+
+	lua.push_function(approve_workshop_update);
+	lua.set_field(-2, lua_string!("ApproveWorkshopUpdate"));
+
+	lua.push_function(get_held_workshop_updates);
+	lua.set_field(-2, lua_string!("GetHeldWorkshopUpdates"));
+
+	lua.push_function(gma_extract);
+	lua.set_field(-2, lua_string!("GMAExtract"));
+
+	lua.push_function(gma_read);
+	lua.set_field(-2, lua_string!("GMARead"));
+
+	lua.push_function(gma_create);
+	lua.set_field(-2, lua_string!("GMACreate"));
+
 	lua.set_global(lua_string!("steamworks"));
 
+	STEAM.with(|steam| {
+		steam.resume_downloads();
+		steam.start_mirror(lua);
+		steam.start_watch(lua);
+	});
+
+	0
+}
+
+#[gmod13_close]
+unsafe fn gmod13_close(lua: gmod::lua::State) -> i32 {
+	STEAM.with(|steam| steam.close(lua));
+
+	config::reset();
+
+	LUA.with(|cell| {
+		cell.set(None);
+	});
+
 	0
 }
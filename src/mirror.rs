@@ -0,0 +1,290 @@
+use crate::{
+	callbacks,
+	workshop::{downloads, Steam},
+};
+use std::{collections::BTreeSet, path::PathBuf, time::Duration};
+use steamworks::{PublishedFileId, QueryOptions};
+
+#[derive(Clone, Debug)]
+pub struct MirrorConfig {
+	/// Workshop ID of the collection to keep in sync with, if any
+	pub collection: Option<u64>,
+	/// Seconds between syncs
+	pub interval: f64,
+	/// Delete items from the cache when they are removed from the collection
+	pub remove: bool,
+}
+impl Default for MirrorConfig {
+	fn default() -> Self {
+		MirrorConfig {
+			collection: None,
+			interval: 300.,
+			remove: false,
+		}
+	}
+}
+impl MirrorConfig {
+	pub fn interval(&self) -> Duration {
+		// Don't hammer Steam
+		crate::config::seconds(self.interval.max(30.))
+	}
+
+	/// Overrides any known keys with the values in the Lua table at `index`
+	pub unsafe fn apply(&mut self, lua: gmod::lua::State, index: i32) {
+		if let Some(collection) = crate::config::get_string(lua, index, lua_string!("collection")) {
+			self.collection = collection.parse().ok().filter(|collection| *collection != 0);
+		}
+		if let Some(interval) = crate::config::get_number(lua, index, lua_string!("interval")) {
+			self.interval = interval;
+		}
+		if let Some(remove) = crate::config::get_bool(lua, index, lua_string!("remove")) {
+			self.remove = remove;
+		}
+	}
+
+	pub unsafe fn push(&self, lua: gmod::lua::State) {
+		lua.new_table();
+
+		if let Some(collection) = self.collection {
+			lua.push_string(&collection.to_string());
+			lua.set_field(-2, lua_string!("collection"));
+		}
+
+		lua.push_number(self.interval);
+		lua.set_field(-2, lua_string!("interval"));
+
+		lua.push_boolean(self.remove);
+		lua.set_field(-2, lua_string!("remove"));
+	}
+}
+
+pub const MIRROR_PATH: &str = "garrysmod/data/gmsv_workshop/mirror.txt";
+
+/// Mirrored collection as of the last sync
+#[derive(Clone, Debug)]
+pub struct MirrorState {
+	pub collection: PublishedFileId,
+	pub children: BTreeSet<PublishedFileId>,
+	/// Items removed from the collection whose cached GMA we still have to delete, once nothing has been handed it
+	pub evict: BTreeSet<PublishedFileId>,
+}
+impl MirrorState {
+	fn new(collection: PublishedFileId) -> MirrorState {
+		MirrorState {
+			collection,
+			children: BTreeSet::new(),
+			evict: BTreeSet::new(),
+		}
+	}
+}
+
+/// Children of the mirrored collection as of the last sync, as `collection child... -evict...`
+fn load_mirror(collection: PublishedFileId) -> Option<MirrorState> {
+	let persisted = std::fs::read_to_string(MIRROR_PATH).ok()?;
+	let mut lines = persisted.split_whitespace();
+	if lines.next()?.parse::<u64>().ok()? != collection.0 {
+		return None;
+	}
+
+	let mut state = MirrorState::new(collection);
+	for line in lines {
+		match line.strip_prefix('-') {
+			Some(id) => state.evict.extend(id.parse::<u64>().ok().map(PublishedFileId)),
+			None => state.children.extend(line.parse::<u64>().ok().map(PublishedFileId))
+		}
+	}
+	Some(state)
+}
+
+fn persist_mirror(state: &MirrorState) {
+	let persisted = std::iter::once(state.collection.to_string())
+		.chain(state.children.iter().map(|id| id.to_string()))
+		.chain(state.evict.iter().map(|id| format!("-{}", id)))
+		.collect::<Vec<_>>()
+		.join("\n");
+
+	let res = PathBuf::from(MIRROR_PATH)
+		.parent()
+		.map(std::fs::create_dir_all)
+		.unwrap_or(Ok(()))
+		.and_then(|_| std::fs::write(MIRROR_PATH, persisted));
+
+	if let Err(err) = res {
+		eprintln!("[gmsv_workshop] Failed to persist mirrored collection: {}", err);
+	}
+}
+
+unsafe fn push_ids(lua: gmod::lua::State, ids: &[PublishedFileId]) {
+	lua.create_table(ids.len() as _, 0);
+	for (i, workshop_id) in ids.iter().enumerate() {
+		lua.push_string(&workshop_id.to_string());
+		lua.raw_seti(-2, (i + 1) as _);
+	}
+}
+
+impl Steam {
+	/// (Re)starts the timer that keeps the configured collection in sync, or stops it if there isn't one
+	pub fn start_mirror(&self, lua: gmod::lua::State) {
+		let config = crate::config::get().mirror;
+
+		unsafe {
+			lua.get_global(lua_string!("timer"));
+			if config.collection.is_some() {
+				lua.get_field(-1, lua_string!("Create"));
+				lua.push_string("gmsv_workshop_mirror");
+				lua.push_number(config.interval().as_secs_f64());
+				lua.push_integer(0);
+				lua.push_function(Self::mirror_tick);
+				lua.call(4, 0);
+			} else {
+				lua.get_field(-1, lua_string!("Remove"));
+				lua.push_string("gmsv_workshop_mirror");
+				lua.call(1, 0);
+			}
+			lua.pop();
+		}
+
+		if config.collection.is_some() {
+			self.sync_mirror();
+		}
+	}
+
+	extern "C-unwind" fn mirror_tick(_lua: gmod::lua::State) -> i32 {
+		crate::STEAM.with(|steam| steam.sync_mirror());
+		0
+	}
+
+	/// Fetches the mirrored collection's children, downloads new ones and drops removed ones
+	pub fn sync_mirror(&self) {
+		let collection = match crate::config::get().mirror.collection {
+			Some(collection) => PublishedFileId(collection),
+			None => return
+		};
+
+		if !self.server.is_logged_in() {
+			// Try again shortly rather than waiting for the next tick
+			unsafe {
+				let lua = crate::lua();
+				lua.get_global(lua_string!("timer"));
+				lua.get_field(-1, lua_string!("Create"));
+				lua.push_string("gmsv_workshop_mirror_retry");
+				lua.push_integer(5);
+				lua.push_integer(1);
+				lua.push_function(Self::mirror_tick);
+				lua.call(4, 0);
+				lua.pop();
+			}
+			return;
+		}
+
+		let query = match self.server.ugc().query_item(collection) {
+			Ok(query) => query,
+			Err(_) => {
+				eprintln!("[gmsv_workshop] Failed to query mirrored collection {}", collection);
+				return;
+			}
+		};
+
+		let max_age = crate::config::get().mirror.interval().as_secs() as u32;
+		query.allow_cached_response(max_age).include_children(true).fetch(move |result| {
+			callbacks::pop();
+
+			let children = match result {
+				Ok(results) if results.get(0).is_some() => results.get_children(0).unwrap_or_default().into_iter().collect::<BTreeSet<_>>(),
+				Ok(_) => {
+					eprintln!("[gmsv_workshop] Mirrored collection {} doesn't exist", collection);
+					return;
+				},
+				Err(err) => {
+					eprintln!("[gmsv_workshop] Failed to query mirrored collection {}: {}", collection, err);
+					return;
+				}
+			};
+
+			crate::STEAM.with(|steam| steam.mirror_synced(collection, children));
+		});
+
+		callbacks::push();
+	}
+
+	fn mirror_synced(&self, collection: PublishedFileId, children: BTreeSet<PublishedFileId>) {
+		// The collection may have been changed while we were waiting for Steam
+		if crate::config::get().mirror.collection != Some(collection.0) {
+			return;
+		}
+
+		let (previous, first_sync) = match self.mirror.take() {
+			Some(previous) if previous.collection == collection => (previous, false),
+			_ => (load_mirror(collection).unwrap_or_else(|| MirrorState::new(collection)), true)
+		};
+
+		let added = children.difference(&previous.children).copied().collect::<Vec<_>>();
+		let removed = previous.children.difference(&children).copied().collect::<Vec<_>>();
+
+		// Only fetch what's new since the last sync, plus anything that went missing from the cache while we were down
+		let mut download = added.clone();
+		if first_sync {
+			download.extend(previous.children.intersection(&children).copied().filter(|workshop_id| !downloads::cache_path(*workshop_id).is_file()));
+		}
+		for workshop_id in download {
+			self.download(workshop_id, downloads::DownloadRequest::new(Vec::new(), Default::default()));
+		}
+
+		let mut evict = previous.evict.clone();
+		if crate::config::get().mirror.remove {
+			evict.extend(removed.iter().copied());
+			// Items put back into the collection are kept
+			evict.retain(|workshop_id| !children.contains(workshop_id));
+			// Anything handed out this session may still be open or mounted, so it waits for a restart
+			evict.retain(|workshop_id| self.watched.borrow().contains_key(workshop_id) || !self.evict(*workshop_id));
+		} else {
+			evict.clear();
+		}
+
+		let state = MirrorState { collection, children, evict };
+		if added.is_empty() && removed.is_empty() && state.evict == previous.evict {
+			self.mirror.replace(Some(state));
+			return;
+		}
+
+		persist_mirror(&state);
+		self.mirror.replace(Some(state));
+
+		if added.is_empty() && removed.is_empty() {
+			return;
+		}
+
+		println!("[gmsv_workshop] Mirrored collection {} changed: {} added, {} removed", collection, added.len(), removed.len());
+
+		unsafe {
+			crate::hooks::run(crate::lua(), "WorkshopCollectionChanged", |lua| {
+				lua.push_string(&collection.to_string());
+				push_ids(lua, &added);
+				push_ids(lua, &removed);
+				3
+			});
+		}
+	}
+
+	/// Deletes an item that was removed from the mirrored collection from the cache
+	///
+	/// Returns whether it's gone, so it can be tried again on a later sync if not
+	fn evict(&self, workshop_id: PublishedFileId) -> bool {
+		let mut evicted = true;
+		for path in [downloads::cache_path(workshop_id), downloads::held_path(workshop_id), downloads::version_path(workshop_id)] {
+			if let Err(err) = std::fs::remove_file(&path) {
+				if err.kind() != std::io::ErrorKind::NotFound {
+					eprintln!("[gmsv_workshop] Failed to remove {}: {}", path.display(), err);
+					evicted = false;
+				}
+			}
+		}
+
+		self.mounts.borrow_mut().untrack(workshop_id);
+
+		if evicted {
+			println!("[gmsv_workshop] Removed {} from the cache", workshop_id);
+		}
+		evicted
+	}
+}
@@ -0,0 +1,100 @@
+use crate::gma::{normalize, Gma};
+use std::collections::{BTreeMap, HashMap};
+use steamworks::PublishedFileId;
+
+/// A path shipped by more than one GMA that we have handed out
+#[derive(Clone, Debug)]
+pub struct Conflict {
+	pub path: String,
+	pub workshop_ids: Vec<PublishedFileId>,
+	/// Whether every GMA ships the same contents, in which case it doesn't matter which one wins
+	pub identical: bool,
+}
+
+/// File tables of every GMA we have handed out
+#[derive(Debug, Default)]
+pub struct MountTable {
+	/// Path -> GMAs that ship it, with the file's CRC
+	paths: HashMap<String, Vec<(PublishedFileId, u32)>>,
+	/// GMA -> paths it ships
+	items: BTreeMap<PublishedFileId, Vec<String>>,
+}
+impl MountTable {
+	/// Records the file table of a GMA, replacing any previous version of it
+	///
+	/// Returns the conflicts this GMA is involved in
+	pub fn track(&mut self, workshop_id: PublishedFileId, gma: &Gma) -> Vec<Conflict> {
+		self.untrack(workshop_id);
+
+		let mut paths = Vec::with_capacity(gma.entries.len());
+		for entry in gma.entries.iter() {
+			let path = normalize(&entry.path).to_ascii_lowercase();
+			let owners = self.paths.entry(path.clone()).or_default();
+			if !owners.iter().any(|(owner, _)| *owner == workshop_id) {
+				owners.push((workshop_id, entry.crc));
+				paths.push(path);
+			}
+		}
+
+		let conflicts = paths.iter().filter_map(|path| self.conflict(path)).collect();
+		self.items.insert(workshop_id, paths);
+		conflicts
+	}
+
+	pub fn is_tracked(&self, workshop_id: PublishedFileId) -> bool {
+		self.items.contains_key(&workshop_id)
+	}
+
+	pub fn untrack(&mut self, workshop_id: PublishedFileId) {
+		for path in self.items.remove(&workshop_id).unwrap_or_default() {
+			if let Some(owners) = self.paths.get_mut(&path) {
+				owners.retain(|(owner, _)| *owner != workshop_id);
+				if owners.is_empty() {
+					self.paths.remove(&path);
+				}
+			}
+		}
+	}
+
+	fn conflict(&self, path: &str) -> Option<Conflict> {
+		let owners = self.paths.get(path)?;
+		if owners.len() < 2 {
+			return None;
+		}
+
+		Some(Conflict {
+			path: path.to_owned(),
+			workshop_ids: owners.iter().map(|(owner, _)| *owner).collect(),
+			identical: owners.iter().all(|(_, crc)| *crc == owners[0].1),
+		})
+	}
+
+	pub fn conflicts(&self) -> Vec<Conflict> {
+		let mut conflicts = self.paths.keys().filter_map(|path| self.conflict(path)).collect::<Vec<_>>();
+		conflicts.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+		conflicts
+	}
+}
+
+/// Pushes `{ { path = string, ids = { string, ... }, identical = bool }, ... }`
+pub unsafe fn push_conflicts(lua: gmod::lua::State, conflicts: &[Conflict]) {
+	lua.create_table(conflicts.len() as _, 0);
+	for (i, conflict) in conflicts.iter().enumerate() {
+		lua.create_table(0, 3);
+
+		lua.push_string(&conflict.path);
+		lua.set_field(-2, lua_string!("path"));
+
+		lua.create_table(conflict.workshop_ids.len() as _, 0);
+		for (j, workshop_id) in conflict.workshop_ids.iter().enumerate() {
+			lua.push_string(&workshop_id.to_string());
+			lua.raw_seti(-2, (j + 1) as _);
+		}
+		lua.set_field(-2, lua_string!("ids"));
+
+		lua.push_boolean(conflict.identical);
+		lua.set_field(-2, lua_string!("identical"));
+
+		lua.raw_seti(-2, (i + 1) as _);
+	}
+}
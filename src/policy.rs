@@ -0,0 +1,179 @@
+use steamworks::QueryResult;
+
+/// Garry's Mod
+const GMOD_APP_ID: u32 = 4000;
+
+#[derive(Clone, Debug)]
+pub enum PolicyViolation {
+	/// We couldn't get the item's metadata, so we can't vouch for it
+	NoMetadata,
+	Banned,
+	/// The item was made for another app
+	App(u32),
+	/// The owner is on the deny list
+	OwnerDenied(u64),
+	/// There is an allow list and the owner isn't on it
+	OwnerNotAllowed(u64),
+	MissingTag(String),
+	ForbiddenTag(String),
+	Score(f32),
+}
+impl std::fmt::Display for PolicyViolation {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			PolicyViolation::NoMetadata => write!(f, "couldn't fetch item metadata"),
+			PolicyViolation::Banned => write!(f, "item is banned"),
+			PolicyViolation::App(app) => write!(f, "item is for app {}", app),
+			PolicyViolation::OwnerDenied(owner) => write!(f, "owner {} is denied", owner),
+			PolicyViolation::OwnerNotAllowed(owner) => write!(f, "owner {} is not allowed", owner),
+			PolicyViolation::MissingTag(tag) => write!(f, "item is missing required tag \"{}\"", tag),
+			PolicyViolation::ForbiddenTag(tag) => write!(f, "item has forbidden tag \"{}\"", tag),
+			PolicyViolation::Score(score) => write!(f, "item score {:.2} is too low", score),
+		}
+	}
+}
+
+/// Parses a list of SteamID64s, logging any entry that isn't one
+///
+/// Lua numbers can't hold a SteamID64 exactly, so they arrive here as something like "7.6561197960288e+16" and are rejected
+fn parse_owners(list: &[String], key: &str) -> Vec<u64> {
+	list.iter()
+		.filter_map(|owner| match owner.trim().parse() {
+			Ok(owner) => Some(owner),
+			Err(_) => {
+				eprintln!("[gmsv_workshop] Ignoring invalid SteamID64 {:?} in policy {} (SteamID64s must be given as strings)", owner, key);
+				None
+			}
+		})
+		.collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct Policy {
+	/// Check every item's metadata against the policy before downloading it
+	pub enabled: bool,
+	/// Only download items from these owners, if set
+	///
+	/// A configured list whose entries all failed to parse stays `Some` and empty, which allows nobody
+	pub allow_owners: Option<Vec<u64>>,
+	/// Never download items from these owners
+	pub deny_owners: Vec<u64>,
+	/// Items must have all of these tags
+	pub require_tags: Vec<String>,
+	/// Items must have none of these tags
+	pub forbid_tags: Vec<String>,
+	/// Consumer app ID items must be made for, 0 to allow any
+	pub app: u32,
+	pub allow_banned: bool,
+	/// Minimum Workshop score, from 0 to 1
+	pub min_score: f32,
+}
+impl Default for Policy {
+	fn default() -> Self {
+		Policy {
+			enabled: false,
+			allow_owners: None,
+			deny_owners: Vec::new(),
+			require_tags: Vec::new(),
+			forbid_tags: Vec::new(),
+			app: GMOD_APP_ID,
+			allow_banned: false,
+			min_score: 0.,
+		}
+	}
+}
+impl Policy {
+	/// Overrides any known keys with the values in the Lua table at `index`
+	pub unsafe fn apply(&mut self, lua: gmod::lua::State, index: i32) {
+		if let Some(enabled) = crate::config::get_bool(lua, index, lua_string!("enabled")) {
+			self.enabled = enabled;
+		}
+		if let Some(allow_owners) = crate::config::get_string_list(lua, index, lua_string!("allow_owners")) {
+			self.allow_owners = if allow_owners.is_empty() {
+				None
+			} else {
+				Some(parse_owners(&allow_owners, "allow_owners"))
+			};
+		}
+		if let Some(deny_owners) = crate::config::get_string_list(lua, index, lua_string!("deny_owners")) {
+			self.deny_owners = parse_owners(&deny_owners, "deny_owners");
+		}
+		if let Some(require_tags) = crate::config::get_string_list(lua, index, lua_string!("require_tags")) {
+			self.require_tags = require_tags;
+		}
+		if let Some(forbid_tags) = crate::config::get_string_list(lua, index, lua_string!("forbid_tags")) {
+			self.forbid_tags = forbid_tags;
+		}
+		if let Some(app) = crate::config::get_number(lua, index, lua_string!("app")) {
+			self.app = app.max(0.) as u32;
+		}
+		if let Some(allow_banned) = crate::config::get_bool(lua, index, lua_string!("allow_banned")) {
+			self.allow_banned = allow_banned;
+		}
+		if let Some(min_score) = crate::config::get_number(lua, index, lua_string!("min_score")) {
+			self.min_score = min_score as f32;
+		}
+	}
+
+	pub unsafe fn push(&self, lua: gmod::lua::State) {
+		lua.new_table();
+
+		lua.push_boolean(self.enabled);
+		lua.set_field(-2, lua_string!("enabled"));
+
+		crate::config::push_string_list(lua, self.allow_owners.iter().flatten().map(|owner| owner.to_string()));
+		lua.set_field(-2, lua_string!("allow_owners"));
+
+		crate::config::push_string_list(lua, self.deny_owners.iter().map(|owner| owner.to_string()));
+		lua.set_field(-2, lua_string!("deny_owners"));
+
+		crate::config::push_string_list(lua, self.require_tags.iter().cloned());
+		lua.set_field(-2, lua_string!("require_tags"));
+
+		crate::config::push_string_list(lua, self.forbid_tags.iter().cloned());
+		lua.set_field(-2, lua_string!("forbid_tags"));
+
+		lua.push_integer(self.app as _);
+		lua.set_field(-2, lua_string!("app"));
+
+		lua.push_boolean(self.allow_banned);
+		lua.set_field(-2, lua_string!("allow_banned"));
+
+		lua.push_number(self.min_score as _);
+		lua.set_field(-2, lua_string!("min_score"));
+	}
+
+	pub fn check(&self, details: &QueryResult) -> Result<(), PolicyViolation> {
+		if details.banned && !self.allow_banned {
+			return Err(PolicyViolation::Banned);
+		}
+
+		let app = details.consumer_app_id.map_or(0, |app| app.0);
+		if self.app != 0 && app != self.app {
+			return Err(PolicyViolation::App(app));
+		}
+
+		let owner = details.owner.raw();
+		if self.deny_owners.contains(&owner) {
+			return Err(PolicyViolation::OwnerDenied(owner));
+		}
+		if self.allow_owners.as_ref().is_some_and(|allow_owners| !allow_owners.contains(&owner)) {
+			return Err(PolicyViolation::OwnerNotAllowed(owner));
+		}
+
+		let has_tag = |tag: &str| details.tags.iter().any(|item_tag| item_tag.trim().eq_ignore_ascii_case(tag));
+
+		if let Some(tag) = self.require_tags.iter().find(|tag| !has_tag(tag)) {
+			return Err(PolicyViolation::MissingTag(tag.clone()));
+		}
+		if let Some(tag) = self.forbid_tags.iter().find(|tag| has_tag(tag)) {
+			return Err(PolicyViolation::ForbiddenTag(tag.clone()));
+		}
+
+		if details.score < self.min_score {
+			return Err(PolicyViolation::Score(details.score));
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,116 @@
+use std::path::Path;
+
+/// Where Steam installs workshop content on a dedicated server
+const CONTENT_PATH: &str = "steamapps/workshop";
+/// Where we write GMAs for `game.MountGMA`
+const CACHE_PATH: &str = "garrysmod/cache/srcds";
+/// LZMA properties, then the decompressed size as a little-endian u64
+const LZMA_PROPS_SIZE: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreflightError {
+	/// The item is bigger than `max_item_size`
+	TooLarge,
+	/// Downloading the item would leave less than `min_free_space` on one of the volumes
+	InsufficientSpace,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PreflightConfig {
+	/// Check the size of every item against free disk space before asking Steam to download it
+	pub enabled: bool,
+	/// Largest item we will download, in bytes, 0 for no limit
+	pub max_item_size: u64,
+	/// Bytes that must remain free on the content and cache volumes after downloading
+	pub min_free_space: u64,
+}
+impl PreflightConfig {
+	/// Overrides any known keys with the values in the Lua table at `index`
+	pub unsafe fn apply(&mut self, lua: gmod::lua::State, index: i32) {
+		if let Some(enabled) = crate::config::get_bool(lua, index, lua_string!("enabled")) {
+			self.enabled = enabled;
+		}
+		if let Some(max_item_size) = crate::config::get_number(lua, index, lua_string!("max_item_size")) {
+			self.max_item_size = max_item_size.max(0.) as u64;
+		}
+		if let Some(min_free_space) = crate::config::get_number(lua, index, lua_string!("min_free_space")) {
+			self.min_free_space = min_free_space.max(0.) as u64;
+		}
+	}
+
+	pub unsafe fn push(&self, lua: gmod::lua::State) {
+		lua.new_table();
+
+		lua.push_boolean(self.enabled);
+		lua.set_field(-2, lua_string!("enabled"));
+
+		lua.push_number(self.max_item_size as _);
+		lua.set_field(-2, lua_string!("max_item_size"));
+
+		lua.push_number(self.min_free_space as _);
+		lua.set_field(-2, lua_string!("min_free_space"));
+	}
+
+	/// Checks whether an item of `size` bytes fits within the limits
+	///
+	/// Steam downloads the item to the content volume, then we write a decompressed copy to the cache volume, so both need room for it.
+	/// `size` is only a lower bound for the decompressed copy, so the cache volume is checked again with [`check_cache`](Self::check_cache)
+	/// once the LZMA header tells us the real size.
+	/// Volumes we can't measure are assumed to have enough space.
+	pub fn check(&self, size: u64) -> Result<(), PreflightError> {
+		if self.max_item_size != 0 && size > self.max_item_size {
+			return Err(PreflightError::TooLarge);
+		}
+
+		let content = crate::disk::existing_ancestor(Path::new(CONTENT_PATH));
+		let cache = crate::disk::existing_ancestor(Path::new(CACHE_PATH));
+
+		let volumes: &[(&Path, u64)] = if crate::disk::same_volume(content, cache) {
+			&[(content, size.saturating_mul(2))]
+		} else {
+			&[(content, size), (cache, size)]
+		};
+
+		for (path, needed) in volumes {
+			self.check_free_space(path, *needed)?;
+		}
+
+		Ok(())
+	}
+
+	/// Checks whether the cache volume has room for a decompressed GMA of `size` bytes
+	pub fn check_cache(&self, size: u64) -> Result<(), PreflightError> {
+		self.check_free_space(crate::disk::existing_ancestor(Path::new(CACHE_PATH)), size)
+	}
+
+	fn check_free_space(&self, path: &Path, needed: u64) -> Result<(), PreflightError> {
+		match crate::disk::free_space(path) {
+			Ok(free) => {
+				if free < needed.saturating_add(self.min_free_space) {
+					return Err(PreflightError::InsufficientSpace);
+				}
+			},
+			Err(err) => eprintln!("[gmsv_workshop] Couldn't check free space on {}: {}", path.display(), err)
+		}
+		Ok(())
+	}
+}
+
+/// Decompressed size from the header of LZMA data, like Steam's `.bin` downloads and `util.Compress`
+pub fn lzma_size(data: &[u8]) -> Option<u64> {
+	data.get(LZMA_PROPS_SIZE..LZMA_PROPS_SIZE + 8).map(|size| u64::from_le_bytes(size.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn lzma_header_size() {
+		let data = vec![7u8; 100_000];
+		let compressed = gmod_lzma::compress(&data, 5).unwrap();
+		assert!((compressed.len() as u64) < data.len() as u64);
+		assert_eq!(lzma_size(&compressed), Some(data.len() as u64));
+		assert_eq!(lzma_size(&compressed[..LZMA_PROPS_SIZE + 7]), None);
+	}
+}
@@ -0,0 +1,87 @@
+use std::{
+	collections::{HashMap, VecDeque},
+	time::{Duration, Instant},
+};
+
+#[derive(Clone, Debug)]
+pub struct QuotaConfig {
+	/// Maximum number of unfinished downloads per caller, 0 for no limit
+	pub in_flight: u32,
+	/// Maximum number of bytes of items a caller may request per window, 0 for no limit
+	pub bytes: u64,
+	/// Length of the `bytes` window, in seconds
+	pub window: f64,
+}
+impl Default for QuotaConfig {
+	fn default() -> Self {
+		QuotaConfig {
+			in_flight: 0,
+			bytes: 0,
+			window: 3600.,
+		}
+	}
+}
+impl QuotaConfig {
+	pub fn window(&self) -> Duration {
+		crate::config::seconds(self.window)
+	}
+
+	/// Overrides any known keys with the values in the Lua table at `index`
+	pub unsafe fn apply(&mut self, lua: gmod::lua::State, index: i32) {
+		if let Some(in_flight) = crate::config::get_number(lua, index, lua_string!("in_flight")) {
+			self.in_flight = in_flight.max(0.) as u32;
+		}
+		if let Some(bytes) = crate::config::get_number(lua, index, lua_string!("bytes")) {
+			self.bytes = bytes.max(0.) as u64;
+		}
+		if let Some(window) = crate::config::get_number(lua, index, lua_string!("window")) {
+			self.window = window.max(0.);
+		}
+	}
+
+	pub unsafe fn push(&self, lua: gmod::lua::State) {
+		lua.new_table();
+
+		lua.push_integer(self.in_flight as _);
+		lua.set_field(-2, lua_string!("in_flight"));
+
+		lua.push_number(self.bytes as _);
+		lua.set_field(-2, lua_string!("bytes"));
+
+		lua.push_number(self.window);
+		lua.set_field(-2, lua_string!("window"));
+	}
+}
+
+/// Bytes of items handed out per caller, for the `bytes` quota
+#[derive(Debug, Default)]
+pub struct QuotaUsage(HashMap<String, VecDeque<(Instant, u64)>>);
+impl QuotaUsage {
+	pub fn record(&mut self, caller: &str, bytes: u64) {
+		self.0.entry(caller.to_owned()).or_default().push_back((Instant::now(), bytes));
+	}
+
+	/// Bytes downloaded by `caller` within the last `window`
+	pub fn bytes(&mut self, caller: &str, window: Duration) -> u64 {
+		let usage = match self.0.get_mut(caller) {
+			Some(usage) => usage,
+			None => return 0
+		};
+
+		let now = Instant::now();
+		while let Some((at, _)) = usage.front() {
+			if now.saturating_duration_since(*at) > window {
+				usage.pop_front();
+			} else {
+				break;
+			}
+		}
+
+		if usage.is_empty() {
+			self.0.remove(caller);
+			return 0;
+		}
+
+		usage.iter().map(|(_, bytes)| *bytes).sum()
+	}
+}
@@ -0,0 +1,177 @@
+use gmod::lua::{LUA_TNUMBER, LUA_TSTRING, LUA_TTABLE};
+use std::time::Duration;
+use steamworks::SteamError;
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+	/// Total number of attempts, including the first
+	pub attempts: u32,
+	/// Seconds to wait before the first retry, doubled for every retry after that
+	pub backoff: f64,
+	/// Upper bound for the backoff, in seconds
+	pub backoff_max: f64,
+	/// Seconds an attempt may take before it counts as timed out, 0 to disable
+	pub timeout: f64,
+	/// Seconds a download may go without any byte progress before it counts as stalled, 0 to disable
+	pub stall_timeout: f64,
+	/// Errors that are worth retrying
+	pub retryable: Vec<SteamError>,
+}
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		RetryPolicy {
+			attempts: 3,
+			backoff: 5.,
+			backoff_max: 60.,
+			timeout: 0.,
+			stall_timeout: 60.,
+			retryable: vec![
+				SteamError::Timeout,
+				SteamError::Busy,
+				SteamError::NoConnection,
+				SteamError::ServiceUnavailable,
+				SteamError::IOFailure,
+				SteamError::TryAnotherCM,
+				SteamError::RateLimitExceeded,
+			],
+		}
+	}
+}
+
+/// Largest `EResult` we look through when parsing error names
+const MAX_ERESULT: i64 = 200;
+
+/// Parses a `SteamError` name (e.g. `"Timeout"`, case insensitive) or `EResult` number
+pub fn parse_error(error: &str) -> Option<SteamError> {
+	if let Ok(code) = error.parse::<i64>() {
+		return SteamError::try_from(code).ok();
+	}
+	(1..=MAX_ERESULT)
+		.filter_map(|code| SteamError::try_from(code).ok())
+		.find(|known| format!("{:?}", known).eq_ignore_ascii_case(error))
+}
+
+impl RetryPolicy {
+	pub fn is_retryable(&self, error: SteamError) -> bool {
+		self.retryable.contains(&error)
+	}
+
+	/// How long to wait before making attempt number `attempt + 1`
+	pub fn delay(&self, attempt: u32) -> Duration {
+		let delay = self.backoff * 2f64.powi(attempt.saturating_sub(1).min(16) as i32);
+		crate::config::seconds(delay.min(self.backoff_max))
+	}
+
+	pub fn timeout(&self) -> Option<Duration> {
+		if self.timeout > 0. {
+			Some(crate::config::seconds(self.timeout))
+		} else {
+			None
+		}
+	}
+
+	pub fn stall_timeout(&self) -> Option<Duration> {
+		if self.stall_timeout > 0. {
+			Some(crate::config::seconds(self.stall_timeout))
+		} else {
+			None
+		}
+	}
+
+	/// Overrides any known keys with the values in the Lua table at `index`
+	///
+	/// `retryable` accepts `SteamError` names (e.g. `"Timeout"`) or `EResult` numbers.
+	pub unsafe fn apply(&mut self, lua: gmod::lua::State, index: i32) {
+		if let Some(attempts) = crate::config::get_number(lua, index, lua_string!("attempts")) {
+			self.attempts = attempts.max(1.) as u32;
+		}
+		if let Some(backoff) = crate::config::get_number(lua, index, lua_string!("backoff")) {
+			self.backoff = backoff.max(0.);
+		}
+		if let Some(backoff_max) = crate::config::get_number(lua, index, lua_string!("backoff_max")) {
+			self.backoff_max = backoff_max.max(0.);
+		}
+		if let Some(timeout) = crate::config::get_number(lua, index, lua_string!("timeout")) {
+			self.timeout = timeout.max(0.);
+		}
+		if let Some(stall_timeout) = crate::config::get_number(lua, index, lua_string!("stall_timeout")) {
+			self.stall_timeout = stall_timeout.max(0.);
+		}
+
+		lua.get_field(index, lua_string!("retryable"));
+		if lua.lua_type(-1) == LUA_TTABLE {
+			self.retryable.clear();
+
+			for i in 1..=lua.len(-1) {
+				lua.raw_geti(-1, i);
+				let error = match lua.lua_type(-1) {
+					LUA_TNUMBER => SteamError::try_from(lua.to_integer(-1) as i64).ok(),
+					LUA_TSTRING => lua.get_string(-1).and_then(|name| {
+						let error = parse_error(&name);
+						if error.is_none() {
+							eprintln!("[gmsv_workshop] Unknown retryable error \"{}\"", name);
+						}
+						error
+					}),
+					_ => None
+				};
+				if let Some(error) = error {
+					self.retryable.push(error);
+				}
+				lua.pop();
+			}
+		}
+		lua.pop();
+	}
+
+	pub unsafe fn push(&self, lua: gmod::lua::State) {
+		lua.new_table();
+
+		lua.push_integer(self.attempts as _);
+		lua.set_field(-2, lua_string!("attempts"));
+
+		lua.push_number(self.backoff);
+		lua.set_field(-2, lua_string!("backoff"));
+
+		lua.push_number(self.backoff_max);
+		lua.set_field(-2, lua_string!("backoff_max"));
+
+		lua.push_number(self.timeout);
+		lua.set_field(-2, lua_string!("timeout"));
+
+		lua.push_number(self.stall_timeout);
+		lua.set_field(-2, lua_string!("stall_timeout"));
+
+		lua.create_table(self.retryable.len() as _, 0);
+		for (i, error) in self.retryable.iter().enumerate() {
+			lua.push_string(&format!("{:?}", error));
+			lua.raw_seti(-2, (i + 1) as _);
+		}
+		lua.set_field(-2, lua_string!("retryable"));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::config::MAX_SECONDS;
+
+	#[test]
+	fn infinite_durations() {
+		let policy = RetryPolicy {
+			backoff: f64::INFINITY,
+			backoff_max: f64::INFINITY,
+			timeout: f64::INFINITY,
+			stall_timeout: 1e30,
+			..Default::default()
+		};
+		let max = Duration::from_secs_f64(MAX_SECONDS);
+		assert_eq!(policy.delay(1), max);
+		assert_eq!(policy.delay(100), max);
+		assert_eq!(policy.timeout(), Some(max));
+		assert_eq!(policy.stall_timeout(), Some(max));
+
+		let policy = RetryPolicy { backoff: 5., backoff_max: f64::NAN, ..Default::default() };
+		assert_eq!(policy.delay(1), Duration::from_secs(5));
+	}
+}
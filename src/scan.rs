@@ -0,0 +1,319 @@
+use crate::gma::Gma;
+
+/// Number of consecutive escape sequences or `string.char` arguments before we call a string obfuscated
+const OBFUSCATION_RUN: usize = 16;
+/// Longest excerpt of a flagged line we report
+const EXCERPT_LEN: usize = 120;
+
+#[derive(Clone, Debug, Default)]
+pub struct ScanConfig {
+	/// Scan the Lua files in every downloaded GMA
+	pub enabled: bool,
+	/// Refuse to hand back GMAs with any findings
+	pub strict: bool,
+	/// Hosts that `http.Fetch`, `http.Post` and `HTTP` may talk to without being flagged
+	pub allowed_hosts: Vec<String>,
+}
+impl ScanConfig {
+	/// Overrides any known keys with the values in the Lua table at `index`
+	pub unsafe fn apply(&mut self, lua: gmod::lua::State, index: i32) {
+		if let Some(enabled) = crate::config::get_bool(lua, index, lua_string!("enabled")) {
+			self.enabled = enabled;
+		}
+		if let Some(strict) = crate::config::get_bool(lua, index, lua_string!("strict")) {
+			self.strict = strict;
+		}
+		if let Some(allowed_hosts) = crate::config::get_string_list(lua, index, lua_string!("allowed_hosts")) {
+			self.allowed_hosts = allowed_hosts.into_iter().map(|host| host.to_ascii_lowercase()).collect();
+		}
+	}
+
+	pub unsafe fn push(&self, lua: gmod::lua::State) {
+		lua.new_table();
+
+		lua.push_boolean(self.enabled);
+		lua.set_field(-2, lua_string!("enabled"));
+
+		lua.push_boolean(self.strict);
+		lua.set_field(-2, lua_string!("strict"));
+
+		crate::config::push_string_list(lua, self.allowed_hosts.iter().cloned());
+		lua.set_field(-2, lua_string!("allowed_hosts"));
+	}
+
+	fn host_allowed(&self, host: &str) -> bool {
+		let host = host.to_ascii_lowercase();
+		self.allowed_hosts.iter().any(|allowed| host == *allowed || host.strip_suffix(allowed.as_str()).map(|sub| sub.ends_with('.')).unwrap_or(false))
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rule {
+	/// `RunString` or `RunStringEx`
+	RunString,
+	/// `CompileString` or `CompileFile`
+	CompileString,
+	/// `game.ConsoleCommand`
+	ConsoleCommand,
+	/// HTTP request to a host that isn't allowed, or to a URL we can't read
+	Http,
+	/// Long runs of escaped bytes or `string.char` arguments
+	Obfuscation,
+}
+impl Rule {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Rule::RunString => "RunString",
+			Rule::CompileString => "CompileString",
+			Rule::ConsoleCommand => "ConsoleCommand",
+			Rule::Http => "HTTP",
+			Rule::Obfuscation => "Obfuscation",
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct Finding {
+	pub file: String,
+	pub line: usize,
+	pub rule: Rule,
+	pub excerpt: String,
+}
+
+/// Scans every Lua file in the GMA for high-risk patterns
+pub fn scan(gma: &Gma, config: &ScanConfig) -> Result<Vec<Finding>, std::io::Error> {
+	let mut findings = Vec::new();
+
+	for entry in gma.entries.iter() {
+		if !entry.path.to_ascii_lowercase().ends_with(".lua") {
+			continue;
+		}
+
+		let src = gma.read(entry)?;
+		let src = String::from_utf8_lossy(&src);
+
+		for (i, line) in src.lines().enumerate() {
+			for rule in scan_line(line, config) {
+				findings.push(Finding {
+					file: entry.path.clone(),
+					line: i + 1,
+					rule,
+					excerpt: excerpt(line),
+				});
+			}
+		}
+	}
+
+	Ok(findings)
+}
+
+fn scan_line(line: &str, config: &ScanConfig) -> Vec<Rule> {
+	let mut rules = Vec::new();
+
+	if has_identifier(line, &["RunString", "RunStringEx"]) {
+		rules.push(Rule::RunString);
+	}
+
+	if has_identifier(line, &["CompileString", "CompileFile"]) {
+		rules.push(Rule::CompileString);
+	}
+
+	if line.contains("game.ConsoleCommand(") {
+		rules.push(Rule::ConsoleCommand);
+	}
+
+	for call in ["http.Fetch(", "http.Post(", "HTTP("] {
+		let mut rest = line;
+		while let Some(pos) = rest.find(call) {
+			rest = &rest[pos + call.len()..];
+			let allowed = url_host(rest).map(|host| config.host_allowed(host)).unwrap_or(false);
+			if !allowed && !rules.contains(&Rule::Http) {
+				rules.push(Rule::Http);
+			}
+		}
+	}
+
+	if is_obfuscated(line) {
+		rules.push(Rule::Obfuscation);
+	}
+
+	rules
+}
+
+/// Whether any of `names` appears as a whole identifier, so that aliases (`local f = RunString`),
+/// spacing (`RunString (code)`) and lookups (`_G["RunString"]`, `_G.RunString`) are caught too
+fn has_identifier(line: &str, names: &[&str]) -> bool {
+	line.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).any(|word| names.contains(&word))
+}
+
+/// Host of the first string literal in `args`, if it's a URL
+fn url_host(args: &str) -> Option<&str> {
+	let start = args.find(['"', '\''])?;
+	let quote = args[start..].chars().next()?;
+	let literal = &args[start + 1..];
+	let literal = &literal[..literal.find(quote)?];
+
+	let rest = literal.strip_prefix("https://").or_else(|| literal.strip_prefix("http://"))?;
+	let host = &rest[..rest.find(['/', ':', '?']).unwrap_or(rest.len())];
+	if host.is_empty() {
+		None
+	} else {
+		Some(host)
+	}
+}
+
+fn is_obfuscated(line: &str) -> bool {
+	// "\x41\x42\x43..." or "\65\66\67..."
+	let bytes = line.as_bytes();
+	let mut run = 0;
+	let mut i = 0;
+	while i < bytes.len() {
+		if bytes[i] == b'\\' {
+			let escape = match bytes.get(i + 1) {
+				Some(b'x') if bytes.get(i + 2).map(u8::is_ascii_hexdigit).unwrap_or(false) => 1 + bytes[i + 2..].iter().take(2).take_while(|b| b.is_ascii_hexdigit()).count(),
+				Some(b) if b.is_ascii_digit() => bytes[i + 1..].iter().take(3).take_while(|b| b.is_ascii_digit()).count(),
+				_ => 0
+			};
+			if escape != 0 {
+				run += 1;
+				if run >= OBFUSCATION_RUN {
+					return true;
+				}
+				i += 1 + escape;
+				continue;
+			}
+		}
+		run = 0;
+		i += 1;
+	}
+
+	// string.char(72, 101, 108, ...)
+	let mut rest = line;
+	while let Some(pos) = rest.find("string.char(") {
+		rest = &rest[pos + "string.char(".len()..];
+		let args = &rest[..rest.find(')').unwrap_or(rest.len())];
+		if args.split(',').filter(|arg| arg.trim().parse::<u32>().is_ok()).count() >= OBFUSCATION_RUN {
+			return true;
+		}
+	}
+
+	false
+}
+
+fn excerpt(line: &str) -> String {
+	let line = line.trim();
+	match line.char_indices().nth(EXCERPT_LEN) {
+		Some((end, _)) => format!("{}...", &line[..end]),
+		None => line.to_owned()
+	}
+}
+
+/// Pushes `{ safe = bool, findings = { { file, line, rule, excerpt }, ... } }`
+pub unsafe fn push_report(lua: gmod::lua::State, findings: &[Finding]) {
+	lua.new_table();
+
+	lua.push_boolean(findings.is_empty());
+	lua.set_field(-2, lua_string!("safe"));
+
+	lua.create_table(findings.len() as _, 0);
+	for (i, finding) in findings.iter().enumerate() {
+		lua.create_table(0, 4);
+
+		lua.push_string(&finding.file);
+		lua.set_field(-2, lua_string!("file"));
+
+		lua.push_integer(finding.line as _);
+		lua.set_field(-2, lua_string!("line"));
+
+		lua.push_string(finding.rule.name());
+		lua.set_field(-2, lua_string!("rule"));
+
+		lua.push_string(&finding.excerpt);
+		lua.set_field(-2, lua_string!("excerpt"));
+
+		lua.raw_seti(-2, (i + 1) as _);
+	}
+	lua.set_field(-2, lua_string!("findings"));
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn config(allowed_hosts: &[&str]) -> ScanConfig {
+		ScanConfig {
+			allowed_hosts: allowed_hosts.iter().map(|host| host.to_string()).collect(),
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn dynamic_code() {
+		let config = config(&[]);
+		assert_eq!(scan_line("RunString(code)", &config), [Rule::RunString]);
+		assert_eq!(scan_line("RunStringEx(code, \"x\")", &config), [Rule::RunString]);
+		assert_eq!(scan_line("local f = CompileString(code, \"x\")", &config), [Rule::CompileString]);
+		assert_eq!(scan_line("CompileFile(\"x.lua\")", &config), [Rule::CompileString]);
+		assert_eq!(scan_line("game.ConsoleCommand(\"rcon_password x\\n\")", &config), [Rule::ConsoleCommand]);
+		assert_eq!(scan_line("MyRunString(code) RunStrings(code) CompileStringEx(code)", &config), []);
+	}
+
+	#[test]
+	fn dynamic_code_aliases() {
+		let config = config(&[]);
+		assert_eq!(scan_line("RunString (code)", &config), [Rule::RunString]);
+		assert_eq!(scan_line("local f = RunString f(code)", &config), [Rule::RunString]);
+		assert_eq!(scan_line("_G[\"RunString\"](code)", &config), [Rule::RunString]);
+		assert_eq!(scan_line("_G['RunStringEx'](code)", &config), [Rule::RunString]);
+		assert_eq!(scan_line("local compile = _G.CompileString", &config), [Rule::CompileString]);
+		assert_eq!(scan_line("pcall(CompileFile, path)", &config), [Rule::CompileString]);
+	}
+
+	#[test]
+	fn http_hosts() {
+		let config = config(&["example.com"]);
+		assert_eq!(scan_line("http.Fetch(\"https://example.com/x\")", &config), []);
+		assert_eq!(scan_line("http.Fetch('http://api.example.com:8080/x')", &config), []);
+		assert_eq!(scan_line("http.Fetch(\"https://badexample.com/x\")", &config), [Rule::Http]);
+		assert_eq!(scan_line("http.Post(\"https://evil.net\", {})", &config), [Rule::Http]);
+		assert_eq!(scan_line("HTTP({ url = url })", &config), [Rule::Http]);
+		assert_eq!(scan_line("http.Fetch(\"https://example.com\") http.Fetch(url)", &config), [Rule::Http]);
+	}
+
+	#[test]
+	fn url_hosts() {
+		assert_eq!(url_host("\"https://example.com/path\")"), Some("example.com"));
+		assert_eq!(url_host("'http://example.com:27015')"), Some("example.com"));
+		assert_eq!(url_host("\"https://example.com?x=1\")"), Some("example.com"));
+		assert_eq!(url_host("\"ftp://example.com\")"), None);
+		assert_eq!(url_host("\"https:///x\")"), None);
+		assert_eq!(url_host("url)"), None);
+	}
+
+	#[test]
+	fn obfuscation() {
+		let config = config(&[]);
+		let hex = "\\x41".repeat(OBFUSCATION_RUN);
+		let dec = "\\65".repeat(OBFUSCATION_RUN);
+		let chars = vec!["72"; OBFUSCATION_RUN].join(", ");
+		assert_eq!(scan_line(&format!("local s = \"{}\"", hex), &config), [Rule::Obfuscation]);
+		assert_eq!(scan_line(&format!("local s = \"{}\"", dec), &config), [Rule::Obfuscation]);
+		assert_eq!(scan_line(&format!("local s = string.char({})", chars), &config), [Rule::Obfuscation]);
+
+		let short = "\\x41".repeat(OBFUSCATION_RUN - 1);
+		let broken = format!("{} {}", short, short);
+		assert!(!is_obfuscated(&format!("\"{}\"", short)));
+		assert!(!is_obfuscated(&broken));
+		assert!(!is_obfuscated("print(\"\\n\\t\\\\\")"));
+	}
+
+	#[test]
+	fn excerpts() {
+		assert_eq!(excerpt("   RunString(x)  "), "RunString(x)");
+
+		let long = "é".repeat(EXCERPT_LEN + 10);
+		let excerpt = excerpt(&long);
+		assert_eq!(excerpt.chars().count(), EXCERPT_LEN + 3);
+		assert!(excerpt.ends_with("..."));
+	}
+}
@@ -0,0 +1,159 @@
+use crate::{
+	callbacks,
+	workshop::{downloads, Steam},
+};
+use std::time::Duration;
+use steamworks::{PublishedFileId, QueryOptions};
+
+#[derive(Clone, Debug)]
+pub struct WatchConfig {
+	/// Periodically check everything we have served for newer versions on the Workshop
+	pub enabled: bool,
+	/// Seconds between checks, also used as the maximum age of Steam's cached responses
+	pub interval: f64,
+}
+impl Default for WatchConfig {
+	fn default() -> Self {
+		WatchConfig {
+			enabled: false,
+			interval: 600.,
+		}
+	}
+}
+impl WatchConfig {
+	pub fn interval(&self) -> Duration {
+		// Don't hammer Steam
+		crate::config::seconds(self.interval.max(60.))
+	}
+
+	/// Overrides any known keys with the values in the Lua table at `index`
+	pub unsafe fn apply(&mut self, lua: gmod::lua::State, index: i32) {
+		if let Some(enabled) = crate::config::get_bool(lua, index, lua_string!("enabled")) {
+			self.enabled = enabled;
+		}
+		if let Some(interval) = crate::config::get_number(lua, index, lua_string!("interval")) {
+			self.interval = interval;
+		}
+	}
+
+	pub unsafe fn push(&self, lua: gmod::lua::State) {
+		lua.new_table();
+
+		lua.push_boolean(self.enabled);
+		lua.set_field(-2, lua_string!("enabled"));
+
+		lua.push_number(self.interval);
+		lua.set_field(-2, lua_string!("interval"));
+	}
+}
+
+impl Steam {
+	/// Remembers an item we have served, along with the version we have of it
+	///
+	/// The version is the `time_updated` recorded when it was cached, or else the one Steam installed.
+	/// If neither is known it's 0, and the first check only records the Workshop's version.
+	pub fn watch(&self, workshop_id: PublishedFileId) {
+		let mut watched = self.watched.borrow_mut();
+		if watched.contains_key(&workshop_id) {
+			return;
+		}
+
+		let version = downloads::cached_version(workshop_id)
+			.or_else(|| self.server.ugc().item_install_info(workshop_id).map(|info| info.timestamp))
+			.unwrap_or(0);
+
+		watched.insert(workshop_id, version);
+	}
+
+	/// (Re)starts the timer that checks served items for updates, or stops it if it's disabled
+	pub fn start_watch(&self, lua: gmod::lua::State) {
+		let config = crate::config::get().watch;
+
+		unsafe {
+			lua.get_global(lua_string!("timer"));
+			if config.enabled {
+				lua.get_field(-1, lua_string!("Create"));
+				lua.push_string("gmsv_workshop_watch");
+				lua.push_number(config.interval().as_secs_f64());
+				lua.push_integer(0);
+				lua.push_function(Self::watch_tick);
+				lua.call(4, 0);
+			} else {
+				lua.get_field(-1, lua_string!("Remove"));
+				lua.push_string("gmsv_workshop_watch");
+				lua.call(1, 0);
+			}
+			lua.pop();
+		}
+	}
+
+	extern "C-unwind" fn watch_tick(_lua: gmod::lua::State) -> i32 {
+		crate::STEAM.with(|steam| steam.check_updates());
+		0
+	}
+
+	/// Queries everything we have served, in batches, and reports items with a newer version on the Workshop
+	pub fn check_updates(&self) {
+		if !self.server.is_logged_in() {
+			return;
+		}
+
+		let items = self.watched.borrow().keys().copied().collect::<Vec<_>>();
+		let max_age = crate::config::get().watch.interval().as_secs() as u32;
+		let ugc = self.server.ugc();
+
+		for items in items.chunks(steamworks::RESULTS_PER_PAGE as usize) {
+			let query = match ugc.query_items(items.to_vec()) {
+				Ok(query) => query,
+				Err(_) => {
+					eprintln!("[gmsv_workshop] Failed to query {} item(s) for updates", items.len());
+					continue;
+				}
+			};
+
+			query.allow_cached_response(max_age).fetch(move |result| {
+				callbacks::pop();
+
+				let results = match result {
+					Ok(results) => results,
+					Err(err) => {
+						eprintln!("[gmsv_workshop] Failed to check for updates: {}", err);
+						return;
+					}
+				};
+
+				let mut updated = Vec::new();
+				crate::STEAM.with(|steam| {
+					let mut watched = steam.watched.borrow_mut();
+					for details in results.iter().flatten() {
+						let workshop_id = details.published_file_id;
+						if let Some(version) = watched.get_mut(&workshop_id) {
+							if *version == 0 {
+								*version = details.time_updated;
+							} else if details.time_updated > *version {
+								updated.push((workshop_id, *version, details.time_updated));
+								// Only report each new version once
+								*version = details.time_updated;
+							}
+						}
+					}
+				});
+
+				for (workshop_id, old, new) in updated {
+					println!("[gmsv_workshop] {} has been updated on the Workshop", workshop_id);
+
+					unsafe {
+						crate::hooks::run(crate::lua(), "WorkshopItemUpdated", |lua| {
+							lua.push_string(&workshop_id.to_string());
+							lua.push_number(old as _);
+							lua.push_number(new as _);
+							3
+						});
+					}
+				}
+			});
+
+			callbacks::push();
+		}
+	}
+}
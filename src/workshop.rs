@@ -1,6 +1,8 @@
 use gmod::lua::LuaReference;
-use std::{cell::RefCell, collections::HashMap, mem::ManuallyDrop, path::PathBuf};
-use steamworks::PublishedFileId;
+use std::{cell::RefCell, collections::{BTreeSet, HashMap}, mem::ManuallyDrop, path::PathBuf};
+use steamworks::{PublishedFileId, QueryOptions};
+
+use crate::callbacks;
 
 macro_rules! check_installed {
 	($ugc:ident, $workshop_id:expr) => {
@@ -17,13 +19,331 @@ macro_rules! check_installed {
 
 pub mod downloads {
 	use super::*;
+	use crate::{policy::PolicyViolation, preflight::PreflightError, retry::RetryPolicy};
+	use std::{collections::hash_map::Entry, time::Instant};
+	use steamworks::SteamError;
+
+	pub const PERSIST_PATH: &str = "garrysmod/data/gmsv_workshop/downloads.txt";
+
+	/// Where legacy items are downloaded to before they go through the GMA cache
+	const LEGACY_PATH: &str = "garrysmod/cache/srcds/legacy";
+
+	#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+	pub enum DownloadPriority {
+		Low,
+		#[default]
+		Normal,
+		/// Also asks Steam to pause its other downloads in favour of this one
+		High,
+	}
+	impl DownloadPriority {
+		pub unsafe fn from_lua(lua: gmod::lua::State, index: i32) -> Option<Self> {
+			match lua.lua_type(index) {
+				gmod::lua::LUA_TNUMBER => Some(match lua.to_integer(index) {
+					..=0 => DownloadPriority::Low,
+					1 => DownloadPriority::Normal,
+					_ => DownloadPriority::High,
+				}),
+				gmod::lua::LUA_TSTRING => match lua.get_string(index)?.to_ascii_lowercase().as_str() {
+					"low" => Some(DownloadPriority::Low),
+					"normal" => Some(DownloadPriority::Normal),
+					"high" => Some(DownloadPriority::High),
+					_ => None
+				},
+				_ => None
+			}
+		}
+
+		fn parse(priority: &str) -> Option<Self> {
+			match priority {
+				"0" => Some(DownloadPriority::Low),
+				"1" => Some(DownloadPriority::Normal),
+				"2" => Some(DownloadPriority::High),
+				_ => None
+			}
+		}
+	}
+
+	#[derive(Clone, Debug)]
+	pub enum DownloadError {
+		Steam(SteamError),
+		/// The caller has too many unfinished downloads
+		QuotaInFlight,
+		/// The caller has downloaded too many bytes within the quota window
+		QuotaBytes,
+		/// The item failed the size and disk space checks
+		Preflight(PreflightError),
+		/// The item is not allowed by the content policy
+		Policy(PolicyViolation),
+		/// Strict mode found risky Lua in the item
+		Scan(Vec<crate::scan::Finding>),
+		/// Strict mode couldn't read the item to scan it
+		ScanFailed,
+	}
+	impl DownloadError {
+		/// `EResult` for Steam errors, negative for our own
+		pub fn code(&self) -> i32 {
+			match self {
+				DownloadError::Steam(error) => Into::<steamworks::sys::EResult>::into(*error) as i32,
+				DownloadError::QuotaInFlight => -10,
+				DownloadError::QuotaBytes => -11,
+				DownloadError::Preflight(PreflightError::TooLarge) => -12,
+				DownloadError::Preflight(PreflightError::InsufficientSpace) => -13,
+				DownloadError::Policy(_) => -14,
+				DownloadError::Scan(_) => -15,
+				DownloadError::ScanFailed => -16,
+			}
+		}
+	}
+	impl std::fmt::Display for DownloadError {
+		fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+			match self {
+				DownloadError::Steam(error) => write!(f, "{}", error),
+				DownloadError::QuotaInFlight => write!(f, "too many downloads in flight for this caller"),
+				DownloadError::QuotaBytes => write!(f, "download quota exceeded for this caller"),
+				DownloadError::Preflight(PreflightError::TooLarge) => write!(f, "item is larger than the maximum item size"),
+				DownloadError::Preflight(PreflightError::InsufficientSpace) => write!(f, "not enough free disk space for this item"),
+				DownloadError::Policy(violation) => write!(f, "refused by content policy: {}", violation),
+				DownloadError::Scan(findings) => write!(f, "risk scan flagged {} line(s)", findings.len()),
+				DownloadError::ScanFailed => write!(f, "couldn't read the item to scan it"),
+			}
+		}
+	}
+
+	#[derive(Clone, Debug)]
+	pub struct DownloadOptions {
+		/// Register the item with `resource.AddWorkshop` once it has downloaded
+		pub addworkshop: bool,
+		pub retry: RetryPolicy,
+		pub priority: DownloadPriority,
+		/// Tag or SteamID that the download counts against for quotas
+		pub caller: Option<String>,
+		/// Check the item's size against free disk space before downloading it
+		pub preflight: bool,
+		/// Scan the Lua files in the GMA for risky patterns
+		pub scan: bool,
+	}
+	impl Default for DownloadOptions {
+		fn default() -> Self {
+			let config = crate::config::get();
+			DownloadOptions {
+				addworkshop: config.addworkshop,
+				retry: config.retry,
+				priority: DownloadPriority::default(),
+				caller: None,
+				preflight: config.preflight.enabled,
+				scan: config.scan.enabled,
+			}
+		}
+	}
+	impl DownloadOptions {
+		/// Reads the options table at `index`, falling back to the module config for anything unset
+		pub unsafe fn from_lua(lua: gmod::lua::State, index: i32) -> Self {
+			let mut options = DownloadOptions::default();
+			if let Some(addworkshop) = crate::config::get_bool(lua, index, lua_string!("addworkshop")) {
+				options.addworkshop = addworkshop;
+			}
+
+			lua.get_field(index, lua_string!("retry"));
+			if lua.lua_type(-1) == gmod::lua::LUA_TTABLE {
+				options.retry.apply(lua, lua.get_top());
+			}
+			lua.pop();
+
+			lua.get_field(index, lua_string!("priority"));
+			if let Some(priority) = DownloadPriority::from_lua(lua, -1) {
+				options.priority = priority;
+			}
+			lua.pop();
+
+			options.caller = crate::config::get_string(lua, index, lua_string!("caller"));
+
+			if let Some(preflight) = crate::config::get_bool(lua, index, lua_string!("preflight")) {
+				options.preflight = preflight;
+			}
+
+			if let Some(scan) = crate::config::get_bool(lua, index, lua_string!("scan")) {
+				options.scan = scan;
+			}
+
+			options
+		}
+
+		/// Tab separated `key=value` fields for `PERSIST_PATH`
+		fn persist(&self) -> String {
+			let fields = [
+				format!("addworkshop={}", self.addworkshop as u8),
+				format!("priority={}", self.priority as u8),
+				format!("preflight={}", self.preflight as u8),
+				format!("scan={}", self.scan as u8),
+				format!("attempts={}", self.retry.attempts),
+				format!("backoff={}", self.retry.backoff),
+				format!("backoff_max={}", self.retry.backoff_max),
+				format!("timeout={}", self.retry.timeout),
+				format!("stall_timeout={}", self.retry.stall_timeout),
+				format!("retryable={}", self.retry.retryable.iter().map(|error| (Into::<steamworks::sys::EResult>::into(*error) as i32).to_string()).collect::<Vec<_>>().join(",")),
+			];
+			fields.join("\t")
+		}
+
+		/// Reads a field written by `persist`, returning `false` if the key isn't known
+		fn restore(&mut self, key: &str, value: &str) -> bool {
+			match key {
+				"addworkshop" => self.addworkshop = value == "1",
+				"priority" => if let Some(priority) = DownloadPriority::parse(value) { self.priority = priority },
+				"preflight" => self.preflight = value == "1",
+				"scan" => self.scan = value == "1",
+				"attempts" => if let Ok(attempts) = value.parse::<u32>() { self.retry.attempts = attempts.max(1) },
+				"backoff" => if let Ok(backoff) = value.parse::<f64>() { self.retry.backoff = backoff.max(0.) },
+				"backoff_max" => if let Ok(backoff_max) = value.parse::<f64>() { self.retry.backoff_max = backoff_max.max(0.) },
+				"timeout" => if let Ok(timeout) = value.parse::<f64>() { self.retry.timeout = timeout.max(0.) },
+				"stall_timeout" => if let Ok(stall_timeout) = value.parse::<f64>() { self.retry.stall_timeout = stall_timeout.max(0.) },
+				"retryable" => self.retry.retryable = value.split(',').filter_map(crate::retry::parse_error).collect(),
+				_ => return false
+			}
+			true
+		}
+	}
+
+	/// Escapes the characters that would break a line of `PERSIST_PATH`
+	fn escape(value: &str) -> String {
+		value.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
+	}
+
+	fn unescape(value: &str) -> String {
+		let mut unescaped = String::with_capacity(value.len());
+		let mut chars = value.chars();
+		while let Some(char) = chars.next() {
+			if char != '\\' {
+				unescaped.push(char);
+				continue;
+			}
+			match chars.next() {
+				Some('t') => unescaped.push('\t'),
+				Some('n') => unescaped.push('\n'),
+				Some('r') => unescaped.push('\r'),
+				Some(char) => unescaped.push(char),
+				None => {}
+			}
+		}
+		unescaped
+	}
+
+	#[derive(Debug)]
+	pub struct DownloadRequest {
+		pub callbacks: Vec<LuaReference>,
+		pub options: DownloadOptions,
+		/// Number of attempts made so far
+		pub attempt: u32,
+		/// When the current attempt started
+		pub started: Instant,
+		/// Bytes Steam had downloaded when we last saw progress
+		pub downloaded: u64,
+		/// When the download last made progress, or another download it may be queued behind did
+		pub progressed: Instant,
+		/// Whether the request has already been checked against the caller's quota
+		pub admitted: bool,
+		/// Whether the item has already passed the size and disk space checks
+		pub preflighted: bool,
+		/// Everyone the download counts against for quotas, more than one if requests from several callers were merged
+		pub callers: Vec<String>,
+		/// Size of the item according to its metadata, once we've looked it up
+		pub size: Option<u64>,
+	}
+	impl DownloadRequest {
+		pub fn new(callbacks: Vec<LuaReference>, options: DownloadOptions) -> Self {
+			DownloadRequest {
+				callbacks,
+				attempt: 0,
+				started: Instant::now(),
+				downloaded: 0,
+				progressed: Instant::now(),
+				admitted: false,
+				preflighted: false,
+				callers: options.caller.iter().cloned().collect(),
+				size: None,
+				options,
+			}
+		}
+
+		/// Folds a duplicate request for the same item into this one
+		fn merge(&mut self, other: DownloadRequest) {
+			self.callbacks.extend(other.callbacks);
+			for caller in other.callers {
+				if !self.callers.contains(&caller) {
+					self.callers.push(caller);
+				}
+			}
+			self.size = self.size.or(other.size);
+			self.options.addworkshop |= other.options.addworkshop;
+			self.options.priority = self.options.priority.max(other.options.priority);
+			self.options.scan |= other.options.scan;
+		}
+	}
+
+	/// Merges `request` into any existing request for the same item
+	fn insert_request(requests: &mut HashMap<PublishedFileId, DownloadRequest>, workshop_id: PublishedFileId, request: DownloadRequest) {
+		match requests.entry(workshop_id) {
+			Entry::Occupied(mut entry) => entry.get_mut().merge(request),
+			Entry::Vacant(entry) => {
+				entry.insert(request);
+			}
+		}
+	}
+
+	/// What happened to the cached GMA
+	#[derive(Debug)]
+	pub enum CacheUpdate {
+		/// The cache already had this version, or had no previous version
+		Cached,
+		/// A previous version was replaced
+		Updated(crate::gma::GmaDiff),
+		/// Lua files changed, so the new version was put aside until an admin approves it
+		Held(crate::gma::GmaDiff),
+	}
+
+	pub fn cache_path(workshop_id: PublishedFileId) -> PathBuf {
+		PathBuf::from(format!("garrysmod/cache/srcds/{}.gma", workshop_id))
+	}
 
-	fn cache_gma<P: Into<PathBuf>>(workshop_id: PublishedFileId, path: P) -> Result<Option<()>, std::io::Error> {
+	/// Where an update that is being held back is kept
+	pub fn held_path(workshop_id: PublishedFileId) -> PathBuf {
+		PathBuf::from(format!("garrysmod/cache/srcds/{}.gma.held", workshop_id))
+	}
+
+	/// Where we keep the `time_updated` of the newest version we cached (or are holding back)
+	pub fn version_path(workshop_id: PublishedFileId) -> PathBuf {
+		PathBuf::from(format!("garrysmod/cache/srcds/{}.gma.version", workshop_id))
+	}
+
+	/// The `time_updated` of the newest version we cached, if we know it
+	pub fn cached_version(workshop_id: PublishedFileId) -> Option<u32> {
+		std::fs::read_to_string(version_path(workshop_id)).ok()?.trim().parse().ok()
+	}
+
+	fn record_version(workshop_id: PublishedFileId, version: u32) {
+		if cached_version(workshop_id) == Some(version) {
+			return;
+		}
+		if let Err(err) = std::fs::write(version_path(workshop_id), version.to_string()) {
+			eprintln!("[gmsv_workshop] Failed to record the cached version of {}: {}", workshop_id, err);
+		}
+	}
+
+	/// Where a GMA refused by the strict risk scan is moved, so it's never served from the cache
+	pub fn quarantine_path(workshop_id: PublishedFileId) -> PathBuf {
+		PathBuf::from(format!("garrysmod/cache/srcds/{}.gma.quarantined", workshop_id))
+	}
+
+	/// Copies or decompresses the item's GMA into the cache
+	///
+	/// With `preflight`, a compressed item is refused with `StorageFull` if its decompressed size won't fit on the cache volume
+	fn cache_gma<P: Into<PathBuf>>(workshop_id: PublishedFileId, path: P, preflight: bool) -> Result<Option<CacheUpdate>, std::io::Error> {
 		let path = path.into();
 
-		let cache_path = PathBuf::from(format!("garrysmod/cache/srcds/{}.gma", workshop_id));
+		let cache_path = cache_path(workshop_id);
 		if path == cache_path {
-			return Ok(Some(()));
+			return Ok(Some(CacheUpdate::Cached));
 		}
 
 		let mut compressed = None;
@@ -46,8 +366,7 @@ pub mod downloads {
 			for (path, ext) in candidates {
 				if std::intrinsics::likely(ext.eq_ignore_ascii_case("gma")) {
 					// We have a GMA!
-					std::fs::copy(&path, cache_path)?;
-					return Ok(Some(()));
+					return write_cache(workshop_id, |tmp| std::fs::copy(&path, tmp).map(|_| ())).map(Some);
 				}
 
 				if std::intrinsics::likely(ext.eq_ignore_ascii_case("bin")) {
@@ -64,8 +383,7 @@ pub mod downloads {
 		} else {
 			if path.extension().map(|ext| ext.eq_ignore_ascii_case("gma")).unwrap_or(false) {
 				// We have a GMA!
-				std::fs::copy(path, cache_path)?;
-				return Ok(Some(()));
+				return write_cache(workshop_id, |tmp| std::fs::copy(&path, tmp).map(|_| ())).map(Some);
 			}
 
 			// Let's try decompressing this
@@ -77,27 +395,226 @@ pub mod downloads {
 			None => return Ok(None)
 		};
 
-		std::fs::create_dir_all("garrysmod/cache/srcds")?;
+		let compressed = std::fs::read(compressed)?;
+		if preflight {
+			if let Some(size) = crate::preflight::lzma_size(&compressed) {
+				if crate::config::get().preflight.check_cache(size).is_err() {
+					eprintln!("[gmsv_workshop] Not enough free space to decompress {} ({} bytes)", workshop_id, size);
+					return Err(std::io::ErrorKind::StorageFull.into());
+				}
+			}
+		}
+
+		let decompressed = gmod_lzma::decompress(&compressed).map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidData))?;
+		if !decompressed.starts_with(b"GMAD") {
+			return Err(std::io::ErrorKind::InvalidData.into());
+		}
+
+		write_cache(workshop_id, |tmp| std::fs::write(tmp, &decompressed)).map(Some)
+	}
+
+	/// Writes to a temporary file next to the cached GMA and only moves it into place once it's complete,
+	/// so that running out of disk space never leaves a truncated GMA behind
+	///
+	/// If there was a previous version, the new one is compared with it, and held back if Lua files changed and `hold_lua_updates` is set
+	fn write_cache<F: FnOnce(&std::path::Path) -> Result<(), std::io::Error>>(workshop_id: PublishedFileId, write: F) -> Result<CacheUpdate, std::io::Error> {
+		let cache_path = cache_path(workshop_id);
+		if let Some(parent) = cache_path.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+
+		let tmp = cache_path.with_extension("gma.tmp");
+		let res = write(&tmp).and_then(|_| {
+			let diff = if cache_path.is_file() {
+				match (crate::gma::Gma::open(&cache_path), crate::gma::Gma::open(&tmp)) {
+					(Ok(old), Ok(new)) => Some(crate::gma::GmaDiff::new(&old, &new)),
+					_ => None
+				}
+			} else {
+				None
+			};
+
+			match diff {
+				Some(diff) if diff.lua_changed() && crate::config::get().hold_lua_updates => {
+					std::fs::rename(&tmp, held_path(workshop_id))?;
+					Ok(CacheUpdate::Held(diff))
+				},
+				Some(diff) => {
+					std::fs::rename(&tmp, &cache_path)?;
+					let _ = std::fs::remove_file(held_path(workshop_id));
+					Ok(CacheUpdate::Updated(diff))
+				},
+				None => {
+					std::fs::rename(&tmp, &cache_path)?;
+					Ok(CacheUpdate::Cached)
+				}
+			}
+		});
+		if res.is_err() {
+			let _ = std::fs::remove_file(&tmp);
+		}
+		res
+	}
+
+	unsafe fn push_error(lua: gmod::lua::State, error: &DownloadError, attempt: u32) {
+		lua.new_table();
+
+		lua.push_integer(error.code() as _);
+		lua.set_field(-2, lua_string!("error"));
+
+		lua.push_string(&error.to_string());
+		lua.set_field(-2, lua_string!("message"));
+
+		lua.push_integer(attempt as _);
+		lua.set_field(-2, lua_string!("attempt"));
+
+		if let DownloadError::Scan(findings) = error {
+			crate::scan::push_report(lua, findings);
+			lua.set_field(-2, lua_string!("report"));
+		}
+	}
+
+	/// Runs the Lua risk scanner over a cached GMA, if it's enabled for this request
+	///
+	/// Returns the findings, or an error if strict mode refuses the GMA
+	fn scan_gma(request: &DownloadRequest, workshop_id: PublishedFileId) -> Result<Option<Vec<crate::scan::Finding>>, DownloadError> {
+		if !request.options.scan {
+			return Ok(None);
+		}
+
+		let config = crate::config::get().scan;
+
+		let findings = match crate::gma::open_cached(workshop_id).and_then(|gma| crate::scan::scan(&gma, &config)) {
+			Ok(findings) => findings,
+			Err(err) => {
+				eprintln!("[gmsv_workshop] Failed to scan {}: {}", workshop_id, err);
+				if !config.strict {
+					return Ok(None);
+				}
+				quarantine(workshop_id);
+				return Err(DownloadError::ScanFailed);
+			}
+		};
+
+		unsafe {
+			crate::hooks::run(crate::lua(), "WorkshopScanReport", |lua| {
+				lua.push_string(&workshop_id.to_string());
+				crate::scan::push_report(lua, &findings);
+				2
+			});
+		}
+
+		if findings.is_empty() {
+			return Ok(Some(findings));
+		}
+
+		eprintln!("[gmsv_workshop] Scan flagged {} line(s) in {}", findings.len(), workshop_id);
+		for finding in findings.iter() {
+			eprintln!("[gmsv_workshop]   {} in {}:{}: {}", finding.rule.name(), finding.file, finding.line, finding.excerpt);
+		}
+
+		if config.strict {
+			quarantine(workshop_id);
+			Err(DownloadError::Scan(findings))
+		} else {
+			Ok(Some(findings))
+		}
+	}
 
-		std::fs::write(&cache_path, {
-			let decompressed = gmod_lzma::decompress(&std::fs::read(compressed)?).map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidData))?;
-			if !decompressed.starts_with(b"GMAD") {
-				return Err(std::io::ErrorKind::InvalidData.into());
+	/// Moves a GMA that strict mode refused out of the cache, replacing any earlier quarantined copy
+	fn quarantine(workshop_id: PublishedFileId) {
+		let cache_path = cache_path(workshop_id);
+		match std::fs::rename(&cache_path, quarantine_path(workshop_id)) {
+			Ok(_) => println!("[gmsv_workshop] Quarantined {} to {}", workshop_id, quarantine_path(workshop_id).display()),
+			Err(err) => {
+				eprintln!("[gmsv_workshop] Failed to quarantine {}: {}", workshop_id, err);
+				if let Err(err) = std::fs::remove_file(&cache_path) {
+					if err.kind() != std::io::ErrorKind::NotFound {
+						eprintln!("[gmsv_workshop] Failed to evict {}: {}", workshop_id, err);
+					}
+				}
 			}
-			decompressed
-		})?;
+		}
+
+		crate::STEAM.with(|steam| steam.mounts.borrow_mut().untrack(workshop_id));
+	}
+
+	fn report_diff(workshop_id: PublishedFileId, diff: &crate::gma::GmaDiff, held: bool) {
+		if diff.is_empty() {
+			return;
+		}
+
+		if held {
+			println!("[gmsv_workshop] Holding back update to {}: Lua files changed ({} added, {} removed, {} modified)", workshop_id, diff.added.len(), diff.removed.len(), diff.modified.len());
+		} else {
+			println!("[gmsv_workshop] Updated {} ({} added, {} removed, {} modified)", workshop_id, diff.added.len(), diff.removed.len(), diff.modified.len());
+		}
 
-		Ok(Some(()))
+		unsafe {
+			crate::hooks::run(crate::lua(), "WorkshopItemDiff", |lua| {
+				lua.push_string(&workshop_id.to_string());
+				diff.push(lua);
+				lua.push_boolean(held);
+				3
+			});
+		}
 	}
 
-	fn callback(lua: gmod::lua::State, callback: Option<LuaReference>, workshop_id: PublishedFileId, folder: Option<String>) {
-		if let Some(callback) = callback {
+	fn callback(lua: gmod::lua::State, request: DownloadRequest, workshop_id: PublishedFileId, folder: Result<String, DownloadError>) {
+		// Steam's install timestamp is the item's `time_updated`, so it identifies the version we're caching
+		let installed = crate::STEAM.with(|steam| steam.server.ugc().item_install_info(workshop_id)).map(|info| info.timestamp);
+
+		let result = folder.and_then(|folder| match cache_gma(workshop_id, folder, request.options.preflight) {
+			Ok(Some(update)) => {
+				if let Some(installed) = installed {
+					record_version(workshop_id, installed);
+				}
+				Ok(update)
+			},
+			Ok(None) => Err(DownloadError::Steam(SteamError::FileNotFound)),
+			Err(err) if err.kind() == std::io::ErrorKind::StorageFull => Err(DownloadError::Preflight(PreflightError::InsufficientSpace)),
+			Err(err) => {
+				eprintln!("[gmsv_workshop] Failed to process download: {}", err);
+				Err(DownloadError::Steam(SteamError::IOFailure))
+			}
+		}).and_then(|update| Ok((update, scan_gma(&request, workshop_id)?)));
+
+		if let Ok((update, _)) = &result {
+			crate::STEAM.with(|steam| {
+				if request.options.addworkshop {
+					steam.register_workshop(workshop_id);
+				}
+
+				if !request.callers.is_empty() {
+					// Cache hits and legacy items count too, they're still content we hand out for the caller
+					let size = request.size.or_else(|| cache_path(workshop_id).metadata().ok().map(|metadata| metadata.len())).unwrap_or(0);
+					let mut usage = steam.quota_usage.borrow_mut();
+					for caller in request.callers.iter() {
+						usage.record(caller, size);
+					}
+				}
+
+				match update {
+					CacheUpdate::Cached => {},
+					CacheUpdate::Updated(diff) => {
+						steam.mounts.borrow_mut().untrack(workshop_id);
+						report_diff(workshop_id, diff, false);
+					},
+					CacheUpdate::Held(diff) => report_diff(workshop_id, diff, true),
+				}
+
+				steam.track_mount(workshop_id);
+				steam.watch(workshop_id);
+			});
+		}
+
+		for callback in request.callbacks {
 			unsafe {
 				lua.from_reference(callback);
 				lua.dereference(callback);
 
-				match folder.map(|folder| cache_gma(workshop_id, folder)) {
-					Some(Ok(Some(_))) => {
+				match result {
+					Ok((_, ref report)) => {
 						let gma = format!("cache/srcds/{}.gma", workshop_id);
 
 						lua.push_string(&gma);
@@ -109,80 +626,162 @@ pub mod downloads {
 						lua.push_string("GAME");
 						lua.call(3, 1);
 						lua.remove(lua.get_top() - 1);
-					},
 
-					Some(Err(err)) => {
-						eprintln!("[gmsv_workshop] Failed to process download: {}", err);
-						lua.push_nil();
-						lua.push_nil();
+						if let Some(report) = report {
+							crate::scan::push_report(lua, report);
+							lua.pcall_ignore(3, 0);
+						} else {
+							lua.pcall_ignore(2, 0);
+						}
 					},
 
-					_ => {
+					Err(ref error) => {
 						lua.push_nil();
 						lua.push_nil();
+						push_error(lua, error, request.attempt);
+
+						lua.pcall_ignore(3, 0);
 					}
 				}
-
-				lua.pcall_ignore(2, 0);
 			}
 		}
 	}
 
 	impl Steam {
-		pub fn download(&self, workshop_id: PublishedFileId, callback: Option<LuaReference>) {
+		pub fn download(&self, workshop_id: PublishedFileId, mut request: DownloadRequest) {
 			let lua = crate::lua();
 			let ugc = self.server.ugc();
 
-			{
-				let cache_path = format!("garrysmod/cache/srcds/{}.gma", workshop_id);
-				if PathBuf::from(&cache_path).is_file() {
-					return self::callback(lua, callback, workshop_id, Some(cache_path));
+			// Cached items are checked too, they count towards the quota like any other
+			if !request.admitted {
+				if let Err(error) = self.check_quota(&request) {
+					eprintln!("[gmsv_workshop] Rejected {} for {}: {}", workshop_id, request.callers.join(", "), error);
+					return self::callback(lua, request, workshop_id, Err(error));
 				}
+				request.admitted = true;
 			}
 
-			if let Some(folder) = check_installed!(ugc, workshop_id) {
-				return self::callback(lua, callback, workshop_id, Some(folder));
+			let local = self.local_copy(workshop_id);
+
+			// The policy has to see cached and installed items too, and the bytes quota needs the item's size
+			// The disk preflight is only worth doing if we're actually going to download something
+			let quota_bytes = crate::config::get().quota.bytes != 0 && !request.callers.is_empty();
+			let needs_metadata = crate::config::get().policy.enabled || quota_bytes || (request.options.preflight && local.is_none());
+			if needs_metadata && !request.preflighted {
+				if !self.server.is_logged_in() {
+					return self.queue(workshop_id, request);
+				}
+				let request = match self.merge_pending(workshop_id, request) {
+					Some(request) => request,
+					None => return
+				};
+				return self.preflight(workshop_id, request);
+			}
+
+			if let Some(folder) = local {
+				return self::callback(lua, request, workshop_id, Ok(folder));
 			}
 
 			if !self.server.is_logged_in() {
-				unsafe {
-					lua.get_global(lua_string!("hook"));
-					lua.get_field(-1, lua_string!("Add"));
-					lua.push_string("Think");
-					lua.push_string("gmsv_workshop_queued");
-					lua.push_function(Self::process_queued);
-					lua.call(3, 0);
-					lua.pop();
-				}
+				return self.queue(workshop_id, request);
+			}
 
-				self.queued.borrow_mut().insert(workshop_id, callback);
+			let mut request = match self.merge_pending(workshop_id, request) {
+				Some(request) => request,
+				None => return
+			};
 
-				println!("[gmsv_workshop] Queued {}", workshop_id);
+			let concurrency = crate::config::get().concurrency;
+			if concurrency != 0 && self.pending.borrow().len() >= concurrency as usize {
+				insert_request(&mut self.scheduled.borrow_mut(), workshop_id, request);
 				return;
 			}
 
+			request.attempt += 1;
+			request.started = Instant::now();
+			request.downloaded = 0;
+			request.progressed = request.started;
+
+			if ugc.item_state(workshop_id).contains(steamworks::ItemState::LEGACY_ITEM) {
+				return self.download_legacy(workshop_id, request);
+			}
+
 			let success = {
 				ugc.suspend_downloads(false);
-				ugc.download_item(workshop_id, true)
+				ugc.download_item(workshop_id, request.options.priority == DownloadPriority::High)
 			};
 			if !success {
 				eprintln!(
 					"[gmsv_workshop] Item ID {} is invalid or the server is not logged onto Steam",
 					workshop_id
 				);
-				return self::callback(lua, callback, workshop_id, None);
+				let error = if self.server.is_logged_in() { SteamError::InvalidParameter } else { SteamError::NoConnection };
+				return self.download_failed(workshop_id, request, error);
 			}
 
 			if let Some(folder) = check_installed!(ugc, workshop_id) {
-				return self::callback(lua, callback, workshop_id, Some(folder));
+				return self::callback(lua, request, workshop_id, Ok(folder));
+			}
+
+			if request.attempt > 1 {
+				println!("[gmsv_workshop] Downloading {} (attempt {}/{})", workshop_id, request.attempt, request.options.retry.attempts);
+			} else {
+				println!("[gmsv_workshop] Downloading {}", workshop_id);
+			}
+
+			insert_request(&mut self.pending.borrow_mut(), workshop_id, request);
+
+			Self::start_polling(lua);
+		}
+
+		/// Where the item can be served from without downloading it, if it's already cached or installed
+		fn local_copy(&self, workshop_id: PublishedFileId) -> Option<String> {
+			let cache_path = cache_path(workshop_id);
+			if cache_path.is_file() && !self.cache_stale(workshop_id) {
+				return Some(cache_path.to_string_lossy().into_owned());
+			}
+
+			let ugc = self.server.ugc();
+			check_installed!(ugc, workshop_id)
+		}
+
+		/// Holds the request until the server has logged onto Steam
+		fn queue(&self, workshop_id: PublishedFileId, request: DownloadRequest) {
+			unsafe {
+				let lua = crate::lua();
+				lua.get_global(lua_string!("hook"));
+				lua.get_field(-1, lua_string!("Add"));
+				lua.push_string("Think");
+				lua.push_string("gmsv_workshop_queued");
+				lua.push_function(Self::process_queued);
+				lua.call(3, 0);
+				lua.pop();
 			}
 
-			println!("[gmsv_workshop] Downloading {}", workshop_id);
+			insert_request(&mut self.queued.borrow_mut(), workshop_id, request);
+
+			println!("[gmsv_workshop] Queued {}", workshop_id);
+		}
 
-			if let Some(callback) = callback {
-				self.pending.borrow_mut().insert(workshop_id, callback);
+		/// Merges the request into the item's download if one is already in progress
+		///
+		/// Returns the request back if there was nothing to merge it into
+		fn merge_pending(&self, workshop_id: PublishedFileId, request: DownloadRequest) -> Option<DownloadRequest> {
+			let mut pending = self.pending.borrow_mut();
+			match pending.get_mut(&workshop_id) {
+				Some(existing) => {
+					// Already downloading, just bump Steam's priority if we need to
+					if request.options.priority == DownloadPriority::High && existing.options.priority < DownloadPriority::High {
+						self.server.ugc().download_item(workshop_id, true);
+					}
+					existing.merge(request);
+					None
+				},
+				None => Some(request)
 			}
+		}
 
+		fn start_polling(lua: gmod::lua::State) {
 			unsafe {
 				lua.get_global(lua_string!("timer"));
 				lua.get_field(-1, lua_string!("Create"));
@@ -195,202 +794,975 @@ pub mod downloads {
 			}
 		}
 
-		extern "C-unwind" fn process_queued(lua: gmod::lua::State) -> i32 {
-			crate::STEAM.with(|steam| {
-				if !steam.server.is_logged_in() {
-					return 0;
-				}
+		/// Schedules a retry if the retry policy allows it, otherwise reports the failure to the callbacks
+		fn download_failed(&self, workshop_id: PublishedFileId, request: DownloadRequest, error: SteamError) {
+			let lua = crate::lua();
 
-				for (workshop_id, callback) in steam.queued.take() {
-					steam.download(workshop_id, callback);
-				}
+			let policy = &request.options.retry;
+			let retrying = request.attempt < policy.attempts && policy.is_retryable(error);
 
-				unsafe {
-					lua.get_global(lua_string!("hook"));
-					lua.get_field(-1, lua_string!("Remove"));
-					lua.push_string("Think");
-					lua.push_string("gmsv_workshop_queued");
-					lua.call(2, 0);
-					lua.pop();
-				}
+			unsafe {
+				crate::hooks::run(lua, "WorkshopDownloadFailed", |lua| {
+					lua.push_string(&workshop_id.to_string());
+					push_error(lua, &DownloadError::Steam(error), request.attempt);
+					lua.push_boolean(retrying);
+					3
+				});
+			}
 
-				0
-			})
-		}
+			if !retrying {
+				eprintln!("[gmsv_workshop] Failed to download {} after {} attempt(s): {}", workshop_id, request.attempt, error);
+				return self::callback(lua, request, workshop_id, Err(DownloadError::Steam(error)));
+			}
 
-		unsafe extern "C-unwind" fn poll(lua: gmod::lua::State) -> i32 {
-			let mut queue = Vec::new();
+			let delay = policy.delay(request.attempt);
+			eprintln!("[gmsv_workshop] Failed to download {} ({}), retrying in {:.1}s", workshop_id, error, delay.as_secs_f64());
 
-			crate::STEAM.with(|steam| {
-				let ugc = steam.server.ugc();
+			self.retrying.borrow_mut().insert(workshop_id, (Instant::now() + delay, request));
 
-				steam.pending.borrow_mut().retain(|workshop_id, callback| {
-					if let Some(folder) = check_installed!(ugc, *workshop_id) {
-						queue.push((*callback, *workshop_id, folder));
-						false
-					} else {
-						true
-					}
-				});
-			});
+			Self::start_polling(lua);
+		}
 
-			for (callback, workshop_id, folder) in queue {
-				self::callback(lua, Some(callback), workshop_id, Some(folder));
+		/// Looks up the item's metadata and checks it against the content policy and preflight limits before it is handed to Steam
+		fn preflight(&self, workshop_id: PublishedFileId, request: DownloadRequest) {
+			{
+				let mut preflight = self.preflight.borrow_mut();
+				if let Some(existing) = preflight.get_mut(&workshop_id) {
+					existing.merge(request);
+					return;
+				}
 			}
 
-			0
-		}
-	}
-}
-
-pub mod query {
-	use std::ffi::CStr;
+			let query = match self.server.ugc().query_item(workshop_id) {
+				Ok(query) => query,
+				Err(_) => return self.check_metadata(workshop_id, request, None)
+			};
 
-use crate::callbacks;
+			self.preflight.borrow_mut().insert(workshop_id, request);
 
-use super::*;
+			query.allow_cached_response(60).fetch(move |result| {
+				callbacks::pop();
 
-	fn callback(lua: gmod::lua::State, callback: LuaReference, workshop_id: PublishedFileId, info: Result<steamworks::QueryResults, Option<steamworks::SteamError>>) {
-		unsafe {
-			lua.from_reference(callback);
-			lua.dereference(callback);
+				crate::STEAM.with(|steam| {
+					let request = match steam.preflight.borrow_mut().remove(&workshop_id) {
+						Some(request) => request,
+						None => return
+					};
 
-			lua.new_table();
+					let details = result.ok().and_then(|results| results.get(0));
+					steam.check_metadata(workshop_id, request, details);
+				});
+			});
 
-			lua.push_string(&workshop_id.0.to_string());
-			lua.set_field(-2, lua_string!("id"));
+			callbacks::push();
+		}
 
-			loop {
-				// NB: No idea where to put `-2 means Failed to send query`
-
-				let (info, children) = match info {
-					Err(None) => {
-						// Failed to create query
-						lua.push_integer(-1);
-						lua.set_field(-2, lua_string!("error"));
-						break;
-					},
+		fn check_metadata(&self, workshop_id: PublishedFileId, mut request: DownloadRequest, details: Option<steamworks::QueryResult>) {
+			request.preflighted = true;
 
-					Err(Some(err)) => {
-						// Failed to get item data from the response
-						lua.push_integer(Into::<steamworks::sys::EResult>::into(err) as i32 as _);
-						lua.set_field(-2, lua_string!("error"));
-						break;
-					},
+			let config = crate::config::get();
 
-					Ok(info) => {
-						let first = info.get(0);
-						let next = info.get(1);
-						match (first, next) {
-							(Some(_), Some(_)) | (None, None) | (None, Some(_)) => {
-								// Received 0 or more than 1 result
-								lua.push_integer(-3);
-								lua.set_field(-2, lua_string!("error"));
-								break;
-							},
-
-							(Some(details), None) => (details, info.get_children(0).unwrap_or_default()),
-						}
-					}
+			if config.policy.enabled {
+				let decision = match &details {
+					Some(details) => config.policy.check(details),
+					None => Err(PolicyViolation::NoMetadata)
 				};
-
-				if info.m_nPublishedFileId == 0 {
-					// Workshop item ID in the response is invalid
-					lua.push_integer(-5);
-					lua.set_field(-2, lua_string!("error"));
-					break;
+				if let Err(violation) = decision {
+					eprintln!("[gmsv_workshop] Policy refused {}: {}", workshop_id, violation);
+					return self::callback(crate::lua(), request, workshop_id, Err(DownloadError::Policy(violation)));
 				}
+				println!("[gmsv_workshop] Policy allowed {}", workshop_id);
+			}
 
-				if info.m_nPublishedFileId != workshop_id.0 {
-					// Workshop item ID in response is mismatching the requested file ID
-					lua.push_integer(-6);
-					lua.set_field(-2, lua_string!("error"));
-					break;
-				}
+			if let Some(details) = &details {
+				request.size = Some(details.file_size as u64);
+			}
 
-				macro_rules! cstr_to_bytes {
-					($expr:expr) => {
-						CStr::from_ptr($expr.as_ptr() as *const _).to_bytes()
-					}
-				}
+			if let Err(error) = self.check_quota_bytes(&request.callers, request.size) {
+				eprintln!("[gmsv_workshop] Rejected {} ({} bytes) for {}: {}", workshop_id, request.size.unwrap_or(0), request.callers.join(", "), error);
+				return self::callback(crate::lua(), request, workshop_id, Err(error));
+			}
+
+			// Nothing to check the disk for if we already have the item
+			if request.options.preflight && self.local_copy(workshop_id).is_none() {
+				match &details {
+					Some(details) => {
+						let size = details.file_size as u64;
+						if let Err(error) = config.preflight.check(size) {
+							let error = DownloadError::Preflight(error);
+							eprintln!("[gmsv_workshop] Refused {} ({} bytes): {}", workshop_id, size, error);
+							return self::callback(crate::lua(), request, workshop_id, Err(error));
+						}
+					},
+					None => eprintln!("[gmsv_workshop] Couldn't query the size of {}, skipping preflight checks", workshop_id)
+				}
+			}
+
+			self.download(workshop_id, request);
+		}
+
+		/// Records the file table of a GMA we're handing out and reports any paths it shares with other GMAs
+		fn track_mount(&self, workshop_id: PublishedFileId) {
+			if self.mounts.borrow().is_tracked(workshop_id) {
+				return;
+			}
+
+			let gma = match crate::gma::open_cached(workshop_id) {
+				Ok(gma) => gma,
+				Err(err) => {
+					eprintln!("[gmsv_workshop] Failed to read the file table of {}: {}", workshop_id, err);
+					return;
+				}
+			};
+
+			let conflicts = self.mounts.borrow_mut().track(workshop_id, &gma);
+			if conflicts.is_empty() {
+				return;
+			}
+
+			eprintln!("[gmsv_workshop] {} shares {} path(s) with other workshop items", workshop_id, conflicts.len());
+
+			unsafe {
+				crate::hooks::run(crate::lua(), "WorkshopMountConflict", |lua| {
+					lua.push_string(&workshop_id.to_string());
+					crate::mounts::push_conflicts(lua, &conflicts);
+					2
+				});
+			}
+		}
+
+		/// Whether Steam has installed a newer version of the item than the one we cached (or are holding back)
+		fn cache_stale(&self, workshop_id: PublishedFileId) -> bool {
+			let ugc = self.server.ugc();
+			let installed = match ugc.item_install_info(workshop_id) {
+				Some(info) if ugc.item_state(workshop_id).contains(steamworks::ItemState::INSTALLED) => info.timestamp,
+				_ => return false
+			};
+
+			// Caches written before we kept versions are refreshed once from the installed copy
+			cached_version(workshop_id) != Some(installed)
+		}
+
+		/// Moves an update that was held back into the cache
+		///
+		/// Returns `false` if no update is being held for the item
+		pub fn approve_update(&self, workshop_id: PublishedFileId) -> bool {
+			if let Err(err) = std::fs::rename(held_path(workshop_id), cache_path(workshop_id)) {
+				if err.kind() != std::io::ErrorKind::NotFound {
+					eprintln!("[gmsv_workshop] Failed to approve update to {}: {}", workshop_id, err);
+				}
+				return false;
+			}
+
+			println!("[gmsv_workshop] Approved update to {}", workshop_id);
+
+			self.mounts.borrow_mut().untrack(workshop_id);
+			self.track_mount(workshop_id);
+
+			true
+		}
+
+		/// Updates that are being held back, with what changed in them
+		pub fn held_updates(&self) -> Vec<(PublishedFileId, crate::gma::GmaDiff)> {
+			let dir = match std::fs::read_dir("garrysmod/cache/srcds") {
+				Ok(dir) => dir,
+				Err(_) => return Vec::new()
+			};
+
+			let mut held = dir
+				.filter_map(|entry| entry.ok())
+				.filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".gma.held")?.parse::<u64>().ok())
+				.map(PublishedFileId)
+				.filter_map(|workshop_id| {
+					let old = crate::gma::Gma::open(cache_path(workshop_id)).ok()?;
+					let new = crate::gma::Gma::open(held_path(workshop_id)).ok()?;
+					Some((workshop_id, crate::gma::GmaDiff::new(&old, &new)))
+				})
+				.collect::<Vec<_>>();
+			held.sort_unstable_by_key(|(workshop_id, _)| *workshop_id);
+			held
+		}
+
+		/// Legacy (pre-UGC) items are a single UGC file rather than an installed folder, so they have to be fetched through ISteamRemoteStorage
+		fn download_legacy(&self, workshop_id: PublishedFileId, request: DownloadRequest) {
+			{
+				let mut legacy = self.legacy.borrow_mut();
+				if let Some(existing) = legacy.get_mut(&workshop_id) {
+					existing.merge(request);
+					return;
+				}
+				legacy.insert(workshop_id, request);
+			}
+
+			println!("[gmsv_workshop] Downloading legacy item {}", workshop_id);
+
+			let query = match self.server.ugc().query_item(workshop_id) {
+				Ok(query) => query,
+				Err(_) => return self.legacy_downloaded(workshop_id, Err(SteamError::NoConnection))
+			};
+
+			query.allow_cached_response(60).fetch(move |result| {
+				callbacks::pop();
+
+				let handle = result
+					.ok()
+					.and_then(|results| results.get(0))
+					.and_then(|details| details.file);
+
+				crate::STEAM.with(|steam| match handle {
+					Some(handle) => steam.ugc_download(workshop_id, handle),
+					None => steam.legacy_downloaded(workshop_id, Err(SteamError::FileNotFound))
+				});
+			});
+
+			callbacks::push();
+		}
+
+		fn ugc_download(&self, workshop_id: PublishedFileId, handle: steamworks::UGCHandle) {
+			// 0 is the highest priority for UGC downloads
+			let priority = match self.legacy.borrow().get(&workshop_id).map(|request| request.options.priority) {
+				Some(DownloadPriority::High) => 0,
+				Some(DownloadPriority::Low) => 2,
+				_ => 1
+			};
+
+			let rs = match self.remote_storage() {
+				Ok(rs) => rs,
+				Err(error) => return self.legacy_downloaded(workshop_id, Err(error))
+			};
+
+			rs.ugc_download(handle, priority, move |result| {
+				callbacks::pop();
+				crate::STEAM.with(|steam| steam.legacy_downloaded(workshop_id, result));
+			});
+
+			callbacks::push();
+		}
+
+		fn legacy_downloaded(&self, workshop_id: PublishedFileId, result: Result<steamworks::UGCDownloadResult, SteamError>) {
+			let request = match self.legacy.borrow_mut().remove(&workshop_id) {
+				Some(request) => request,
+				None => return
+			};
+
+			let path = result.and_then(|download| {
+				let data = self.read_ugc(&download)?;
+
+				// Keep the extension so that the GMA cache knows whether it needs decompressing
+				let ext = if download.file_name.to_ascii_lowercase().ends_with(".gma") { "gma" } else { "bin" };
+				let path = PathBuf::from(format!("{}/{}.{}", LEGACY_PATH, workshop_id, ext));
+
+				std::fs::create_dir_all(LEGACY_PATH).and_then(|_| std::fs::write(&path, data)).map_err(|err| {
+					eprintln!("[gmsv_workshop] Failed to save legacy item {}: {}", workshop_id, err);
+					SteamError::IOFailure
+				})?;
+
+				Ok(path)
+			});
+
+			match path {
+				Ok(path) => {
+					self::callback(crate::lua(), request, workshop_id, Ok(path.to_string_lossy().into_owned()));
+					let _ = std::fs::remove_file(path);
+				},
+				Err(error) => self.download_failed(workshop_id, request, error)
+			}
+		}
+
+		/// Checks a new request against its callers' quotas
+		fn check_quota(&self, request: &DownloadRequest) -> Result<(), DownloadError> {
+			let quota = crate::config::get().quota;
+
+			if quota.in_flight != 0 && request.callers.iter().any(|caller| self.caller_in_flight(caller) >= quota.in_flight as usize) {
+				return Err(DownloadError::QuotaInFlight);
+			}
+
+			self.check_quota_bytes(&request.callers, request.size)
+		}
+
+		/// Checks whether downloading `size` more bytes would take any of `callers` over the bytes quota
+		///
+		/// Without a size, only callers who are already at their quota are refused.
+		fn check_quota_bytes(&self, callers: &[String], size: Option<u64>) -> Result<(), DownloadError> {
+			let quota = crate::config::get().quota;
+			if quota.bytes == 0 {
+				return Ok(());
+			}
+
+			let mut usage = self.quota_usage.borrow_mut();
+			let over = callers.iter().any(|caller| {
+				let used = usage.bytes(caller, quota.window());
+				match size {
+					Some(size) => used.saturating_add(size) > quota.bytes,
+					None => used >= quota.bytes
+				}
+			});
+			if over {
+				Err(DownloadError::QuotaBytes)
+			} else {
+				Ok(())
+			}
+		}
+
+		/// Number of unfinished downloads that were requested by `caller`
+		pub fn caller_in_flight(&self, caller: &str) -> usize {
+			let is_caller = |request: &DownloadRequest| request.admitted && request.callers.iter().any(|requester| requester == caller);
+
+			[&self.pending, &self.scheduled, &self.queued, &self.preflight, &self.legacy]
+				.into_iter()
+				.map(|requests| requests.borrow().values().filter(|request| is_caller(request)).count())
+				.sum::<usize>()
+				+ self.retrying.borrow().values().filter(|(_, request)| is_caller(request)).count()
+		}
+
+		/// Starts as many scheduled downloads as the concurrency limit allows, highest priority first
+		fn dispatch_scheduled(&self) {
+			loop {
+				let concurrency = crate::config::get().concurrency;
+				if concurrency != 0 && self.pending.borrow().len() >= concurrency as usize {
+					break;
+				}
+
+				let next = {
+					let mut scheduled = self.scheduled.borrow_mut();
+					let next = scheduled
+						.iter()
+						.max_by(|(_, a), (_, b)| a.options.priority.cmp(&b.options.priority).then(b.started.cmp(&a.started)))
+						.map(|(workshop_id, _)| *workshop_id);
+
+					match next.and_then(|workshop_id| Some((workshop_id, scheduled.remove(&workshop_id)?))) {
+						Some(next) => next,
+						None => break
+					}
+				};
+
+				self.download(next.0, next.1);
+			}
+		}
+
+		/// Changes the priority of a download that hasn't finished yet
+		///
+		/// Returns `false` if the item isn't being downloaded
+		pub fn set_download_priority(&self, workshop_id: PublishedFileId, priority: DownloadPriority) -> bool {
+			if let Some(request) = self.pending.borrow_mut().get_mut(&workshop_id) {
+				if priority == DownloadPriority::High && request.options.priority < DownloadPriority::High {
+					self.server.ugc().download_item(workshop_id, true);
+				}
+				request.options.priority = priority;
+				return true;
+			}
+
+			for requests in [&self.scheduled, &self.queued, &self.preflight, &self.legacy] {
+				if let Some(request) = requests.borrow_mut().get_mut(&workshop_id) {
+					request.options.priority = priority;
+					return true;
+				}
+			}
+
+			if let Some((_, request)) = self.retrying.borrow_mut().get_mut(&workshop_id) {
+				request.options.priority = priority;
+				return true;
+			}
+
+			false
+		}
+
+		/// Attaches another callback to an in-flight download
+		///
+		/// Returns `false` (without taking ownership of the callback) if the item isn't being downloaded
+		pub fn attach_download(&self, workshop_id: PublishedFileId, callback: LuaReference) -> bool {
+			for requests in [&self.pending, &self.scheduled, &self.queued, &self.preflight, &self.legacy] {
+				if let Some(request) = requests.borrow_mut().get_mut(&workshop_id) {
+					request.callbacks.push(callback);
+					return true;
+				}
+			}
+			if let Some((_, request)) = self.retrying.borrow_mut().get_mut(&workshop_id) {
+				request.callbacks.push(callback);
+				return true;
+			}
+			false
+		}
+
+		pub fn in_flight(&self) -> Vec<PublishedFileId> {
+			let mut in_flight = self.pending.borrow().keys()
+				.chain(self.scheduled.borrow().keys())
+				.chain(self.queued.borrow().keys())
+				.chain(self.preflight.borrow().keys())
+				.chain(self.legacy.borrow().keys())
+				.chain(self.retrying.borrow().keys())
+				.copied()
+				.collect::<Vec<_>>();
+			in_flight.sort_unstable();
+			in_flight.dedup();
+			in_flight
+		}
+
+		/// Writes every in-flight download to disk and releases their callbacks, so that they can be resumed by the next Lua state
+		pub fn persist_downloads(&self, lua: gmod::lua::State) {
+			let requests = self.pending.take().into_iter()
+				.chain(self.scheduled.take())
+				.chain(self.queued.take())
+				.chain(self.preflight.take())
+				.chain(self.legacy.take())
+				.chain(self.retrying.take().into_iter().map(|(workshop_id, (_, request))| (workshop_id, request)))
+				.collect::<Vec<_>>();
+
+			let mut persisted = String::new();
+			for (workshop_id, request) in requests {
+				persisted.push_str(&format!("{}\tattempt={}\tadmitted={}\t{}", workshop_id, request.attempt, request.admitted as u8, request.options.persist()));
+				for caller in request.callers.iter() {
+					persisted.push_str(&format!("\tcaller={}", escape(caller)));
+				}
+				persisted.push('\n');
+
+				for callback in request.callbacks {
+					unsafe { lua.dereference(callback) };
+				}
+			}
+
+			if persisted.is_empty() {
+				return;
+			}
+
+			let res = PathBuf::from(PERSIST_PATH)
+				.parent()
+				.map(std::fs::create_dir_all)
+				.unwrap_or(Ok(()))
+				.and_then(|_| std::fs::write(PERSIST_PATH, persisted));
+
+			if let Err(err) = res {
+				eprintln!("[gmsv_workshop] Failed to persist in-flight downloads: {}", err);
+			}
+		}
+
+		/// Restarts any downloads that were in-flight when the previous Lua state closed
+		pub fn resume_downloads(&self) {
+			let persisted = match std::fs::read_to_string(PERSIST_PATH) {
+				Ok(persisted) => persisted,
+				Err(_) => return
+			};
+
+			let _ = std::fs::remove_file(PERSIST_PATH);
+
+			for line in persisted.lines() {
+				let mut split = line.split('\t');
+
+				let workshop_id = match split.next().and_then(|workshop_id| workshop_id.trim().parse::<u64>().ok()) {
+					Some(workshop_id) => PublishedFileId(workshop_id),
+					None => continue
+				};
+
+				let mut request = DownloadRequest::new(Vec::new(), DownloadOptions::default());
+				for field in split {
+					let (key, value) = match field.split_once('=') {
+						Some(field) => field,
+						None => continue
+					};
+					match key {
+						"attempt" => request.attempt = value.parse().unwrap_or(0),
+						// Already counted against the caller's quota before the restart
+						"admitted" => request.admitted = value == "1",
+						"caller" => {
+							let caller = unescape(value);
+							request.options.caller.get_or_insert_with(|| caller.clone());
+							request.callers.push(caller);
+						},
+						_ => if !request.options.restore(key, value) {
+							eprintln!("[gmsv_workshop] Ignoring unknown field \"{}\" for {} in {}", key, workshop_id, PERSIST_PATH);
+						}
+					}
+				}
+
+				println!("[gmsv_workshop] Resuming {}", workshop_id);
+
+				self.download(workshop_id, request);
+			}
+		}
+
+		extern "C-unwind" fn process_queued(lua: gmod::lua::State) -> i32 {
+			crate::STEAM.with(|steam| {
+				if !steam.server.is_logged_in() {
+					return 0;
+				}
+
+				for (workshop_id, request) in steam.queued.take() {
+					steam.download(workshop_id, request);
+				}
+
+				unsafe {
+					lua.get_global(lua_string!("hook"));
+					lua.get_field(-1, lua_string!("Remove"));
+					lua.push_string("Think");
+					lua.push_string("gmsv_workshop_queued");
+					lua.call(2, 0);
+					lua.pop();
+				}
+
+				0
+			})
+		}
+
+		unsafe extern "C-unwind" fn poll(lua: gmod::lua::State) -> i32 {
+			let mut queue = Vec::new();
+			let mut failed = Vec::new();
+			let mut retry = Vec::new();
+			let mut legacy = Vec::new();
+
+			crate::STEAM.with(|steam| {
+				let ugc = steam.server.ugc();
+				let logged_in = steam.server.is_logged_in();
+				let now = Instant::now();
+
+				{
+					let mut pending = steam.pending.borrow_mut();
+
+					// Steam works through its download queue in order, so progress on any item means ours are still moving
+					let mut any_progress = false;
+					for (workshop_id, request) in pending.iter_mut() {
+						if let Some((downloaded, _)) = ugc.item_download_info(*workshop_id) {
+							if downloaded > request.downloaded {
+								request.downloaded = downloaded;
+								any_progress = true;
+							}
+						}
+					}
+					if any_progress {
+						for request in pending.values_mut() {
+							request.progressed = now;
+						}
+					}
+
+					let mut installed = Vec::new();
+					let mut errored = Vec::new();
+					let mut legacy_items = Vec::new();
+					for (workshop_id, request) in pending.iter() {
+						if let Some(folder) = check_installed!(ugc, *workshop_id) {
+							installed.push((*workshop_id, folder));
+							continue;
+						}
+
+						if ugc.item_state(*workshop_id).contains(steamworks::ItemState::LEGACY_ITEM) {
+							// Steam will never install these
+							legacy_items.push(*workshop_id);
+							continue;
+						}
+
+						let elapsed = now.saturating_duration_since(request.started);
+
+						let stalled = request.options.retry.stall_timeout().map(|timeout| now.saturating_duration_since(request.progressed) >= timeout).unwrap_or(false);
+						let timed_out = request.options.retry.timeout().map(|timeout| elapsed >= timeout).unwrap_or(false);
+
+						// Losing the connection for a moment shouldn't fail anything, so offline downloads only fail once they stall
+						if stalled || timed_out {
+							errored.push((*workshop_id, if logged_in { SteamError::Timeout } else { SteamError::NoConnection }));
+						}
+					}
+
+					for (workshop_id, folder) in installed {
+						if let Some(request) = pending.remove(&workshop_id) {
+							queue.push((request, workshop_id, folder));
+						}
+					}
+
+					for (workshop_id, error) in errored {
+						if let Some(request) = pending.remove(&workshop_id) {
+							failed.push((workshop_id, request, error));
+						}
+					}
+
+					for workshop_id in legacy_items {
+						if let Some(request) = pending.remove(&workshop_id) {
+							legacy.push((workshop_id, request));
+						}
+					}
+				}
+
+				retry.extend(
+					steam.retrying.borrow_mut()
+						.extract_if(|_, (retry_at, _)| *retry_at <= now)
+						.map(|(workshop_id, (_, request))| (workshop_id, request))
+				);
+			});
+
+			for (request, workshop_id, folder) in queue {
+				self::callback(lua, request, workshop_id, Ok(folder));
+			}
+
+			crate::STEAM.with(|steam| {
+				for (workshop_id, request, error) in failed {
+					steam.download_failed(workshop_id, request, error);
+				}
+
+				for (workshop_id, request) in retry {
+					steam.download(workshop_id, request);
+				}
+
+				for (workshop_id, request) in legacy {
+					steam.download_legacy(workshop_id, request);
+				}
+
+				steam.dispatch_scheduled();
+			});
+
+			0
+		}
+	}
+}
+
+pub mod query {
+	use super::*;
+
+	use crate::info_cache::{FileInfo, InfoKey};
+
+	// NB: No idea where to put `-2 means Failed to send query`
+	fn file_info_result(workshop_id: PublishedFileId, info: Result<steamworks::QueryResults, Option<steamworks::SteamError>>) -> Result<(steamworks::QueryResult, Vec<PublishedFileId>), i32> {
+		match info {
+			// Failed to create query
+			Err(None) => Err(-1),
+
+			// Failed to get item data from the response
+			Err(Some(err)) => Err(Into::<steamworks::sys::EResult>::into(err) as i32),
+
+			Ok(info) => {
+				if info.get(1).is_some() {
+					// Received more than 1 result
+					return Err(-3);
+				}
+				check_details(workshop_id, &info, 0)
+			}
+		}
+	}
+
+	/// Checks the result at `index` of a query is the item we asked about
+	fn check_details(workshop_id: PublishedFileId, info: &steamworks::QueryResults, index: u32) -> Result<(steamworks::QueryResult, Vec<PublishedFileId>), i32> {
+		let (info, children) = match info.get(index) {
+			Some(details) => (details, info.get_children(index).unwrap_or_default()),
+			// Received no result
+			None => return Err(-3),
+		};
+
+		if info.published_file_id.0 == 0 {
+			// Workshop item ID in the response is invalid
+			return Err(-5);
+		}
+
+		if info.published_file_id != workshop_id {
+			// Workshop item ID in response is mismatching the requested file ID
+			return Err(-6);
+		}
+
+		Ok((info, children))
+	}
+
+	#[derive(Clone, Debug)]
+	pub struct FileInfoOptions {
+		pub description: crate::bbcode::DescriptionFormat,
+		/// Maximum number of characters of description text, 0 for no limit
+		pub description_length: usize,
+		/// Steam API language code to get the title and description in, such as `german`
+		pub language: Option<String>,
+	}
+	impl Default for FileInfoOptions {
+		fn default() -> Self {
+			FileInfoOptions {
+				description: Default::default(),
+				description_length: 0,
+				language: crate::config::get().file_info.language,
+			}
+		}
+	}
+	impl FileInfoOptions {
+		/// Reads the options table at `index`, falling back to the module config for anything unset
+		pub unsafe fn from_lua(lua: gmod::lua::State, index: i32) -> Self {
+			let mut options = FileInfoOptions::default();
+			if let Some(description) = crate::config::get_string(lua, index, lua_string!("description")) {
+				match crate::bbcode::DescriptionFormat::parse(&description) {
+					Some(description) => options.description = description,
+					None => lua.error("bad description format (expected \"raw\", \"plain\", \"html\" or \"structured\")")
+				}
+			}
+			if let Some(description_length) = crate::config::get_number(lua, index, lua_string!("description_length")) {
+				options.description_length = description_length.max(0.) as usize;
+			}
+			if let Some(language) = crate::config::get_string(lua, index, lua_string!("language")) {
+				options.language = Some(language.to_ascii_lowercase()).filter(|language| !language.is_empty());
+			}
+			options
+		}
+	}
+
+	fn callback(lua: gmod::lua::State, callback: LuaReference, workshop_id: PublishedFileId, info: &FileInfo, options: &FileInfoOptions) {
+		unsafe {
+			lua.from_reference(callback);
+			lua.dereference(callback);
+
+			push_file_info(lua, workshop_id, info, options);
+
+			lua.pcall_ignore(1, 0);
+		}
+	}
+
+	/// Pushes the table a `steamworks.FileInfo` callback receives
+	unsafe fn push_file_info(lua: gmod::lua::State, workshop_id: PublishedFileId, info: &FileInfo, options: &FileInfoOptions) {
+		lua.new_table();
+
+		lua.push_string(&workshop_id.0.to_string());
+		lua.set_field(-2, lua_string!("id"));
+
+		// Steam doesn't say whether it had a translation, so all we can report is what we asked for
+		lua.push_string(options.language.as_deref().unwrap_or("default"));
+		lua.set_field(-2, lua_string!("requested_language"));
+
+		loop {
+			let (info, children, required_apps) = match info {
+				Err(error) => {
+					lua.push_integer(*error as _);
+					lua.set_field(-2, lua_string!("error"));
+					break;
+				},
+
+				Ok((info, children, required_apps)) => (info, children.clone(), required_apps),
+			};
+
+			lua.push_binary_string(&info.raw_title);
+			lua.set_field(-2, lua_string!("title"));
+
+			crate::bbcode::push(lua, &info.raw_description, options.description, options.description_length);
+			lua.set_field(-2, lua_string!("description"));
+
+			lua.push_string(&info.owner.raw().to_string());
+			lua.set_field(-2, lua_string!("owner"));
+
+			lua.push_string(&info.preview_file.map_or(0, |handle| handle.0).to_string());
+			lua.set_field(-2, lua_string!("previewid"));
+
+			lua.push_string(&info.file.map_or(0, |handle| handle.0).to_string());
+			lua.set_field(-2, lua_string!("fileid"));
+
+			lua.push_binary_string(&info.raw_tags);
+			lua.set_field(-2, lua_string!("tags"));
+
+			lua.push_boolean(info.banned);
+			lua.set_field(-2, lua_string!("banned"));
+
+			lua.push_number(info.time_created as _);
+			lua.set_field(-2, lua_string!("created"));
+
+			lua.push_number(info.time_updated as _);
+			lua.set_field(-2, lua_string!("updated"));
+
+			lua.push_number(info.file_size as _);
+			lua.set_field(-2, lua_string!("size"));
+
+			lua.push_binary_string(&info.raw_url);
+			lua.set_field(-2, lua_string!("previewurl"));
+
+			lua.push_number(info.preview_file_size as _);
+			lua.set_field(-2, lua_string!("previewsize"));
+
+			lua.push_number(info.num_upvotes as _);
+			lua.set_field(-2, lua_string!("up"));
+
+			lua.push_number(info.num_downvotes as _);
+			lua.set_field(-2, lua_string!("down"));
 
-				lua.push_binary_string(cstr_to_bytes!(info.m_rgchTitle));
-				lua.set_field(-2, lua_string!("title"));
+			lua.push_number((info.num_upvotes as u64 + info.num_downvotes as u64) as _);
+			lua.set_field(-2, lua_string!("total"));
 
-				lua.push_binary_string(cstr_to_bytes!(info.m_rgchDescription));
-				lua.set_field(-2, lua_string!("description"));
+			lua.push_number(info.score as _);
+			lua.set_field(-2, lua_string!("score"));
 
-				// On Linux64 Valve packs and aligns the struct to 4 bytes, we need to do an unaligned read on some fields :(
-				#[cfg(all(target_os = "linux", target_pointer_width = "64"))] {
-					lua.push_string(&std::ptr::read_unaligned(std::ptr::addr_of!(info.m_ulSteamIDOwner)).to_string());
-					lua.set_field(-2, lua_string!("owner"));
+			lua.create_table(children.len() as _, 0);
+			for (i, child) in children.into_iter().enumerate() {
+				lua.push_string(&child.0.to_string());
+				lua.raw_seti(-2, (i + 1) as _);
+			}
+			lua.set_field(-2, lua_string!("children"));
 
-					lua.push_string(&std::ptr::read_unaligned(std::ptr::addr_of!(info.m_hPreviewFile)).to_string());
-					lua.set_field(-2, lua_string!("previewid"));
+			if let Some(required_apps) = required_apps {
+				lua.create_table(required_apps.app_ids.len() as _, 0);
+				for (i, app_id) in required_apps.app_ids.iter().enumerate() {
+					lua.push_integer(app_id.0 as _);
+					lua.raw_seti(-2, (i + 1) as _);
+				}
+				lua.set_field(-2, lua_string!("requiredApps"));
 
-					lua.push_string(&std::ptr::read_unaligned(std::ptr::addr_of!(info.m_hFile)).to_string());
-					lua.set_field(-2, lua_string!("fileid"));
+				if required_apps.truncated() {
+					lua.push_boolean(true);
+					lua.set_field(-2, lua_string!("requiredAppsTruncated"));
 				}
-				#[cfg(not(all(target_os = "linux", target_pointer_width = "64")))] {
-					lua.push_string(&info.m_ulSteamIDOwner.to_string());
-					lua.set_field(-2, lua_string!("owner"));
+			}
+
+			break;
+		}
+	}
+
+	/// A `steamworks.FileInfo` call for several items, which calls back once all of them are ready
+	pub struct InfoBatch {
+		callback: std::cell::Cell<Option<LuaReference>>,
+		options: FileInfoOptions,
+		items: Vec<PublishedFileId>,
+		results: RefCell<Vec<Option<FileInfo>>>,
+	}
+	impl InfoBatch {
+		fn ready(&self, index: usize, info: &FileInfo) {
+			self.results.borrow_mut()[index] = Some(info.clone());
+			self.finish();
+		}
+
+		/// Calls back if every item is ready
+		fn finish(&self) {
+			let results = self.results.borrow();
+			if results.iter().any(Option::is_none) {
+				return;
+			}
+
+			let callback = match self.callback.take() {
+				Some(callback) => callback,
+				None => return
+			};
 
-					lua.push_string(&info.m_hPreviewFile.to_string());
-					lua.set_field(-2, lua_string!("previewid"));
+			unsafe {
+				let lua = crate::lua();
+				lua.from_reference(callback);
+				lua.dereference(callback);
 
-					lua.push_string(&info.m_hFile.to_string());
-					lua.set_field(-2, lua_string!("fileid"));
+				lua.create_table(results.len() as _, 0);
+				for (i, (workshop_id, info)) in self.items.iter().zip(results.iter()).enumerate() {
+					if let Some(info) = info {
+						push_file_info(lua, *workshop_id, info, &self.options);
+						lua.raw_seti(-2, (i + 1) as _);
+					}
 				}
 
-				lua.push_binary_string(cstr_to_bytes!(info.m_rgchTags));
-				lua.set_field(-2, lua_string!("tags"));
+				lua.pcall_ignore(1, 0);
+			}
+		}
+
+		/// Releases the callback if the batch never finished
+		pub unsafe fn abandon(&self, lua: gmod::lua::State) {
+			if let Some(callback) = self.callback.take() {
+				lua.dereference(callback);
+			}
+		}
+	}
 
-				lua.push_boolean(info.m_bBanned);
-				lua.set_field(-2, lua_string!("banned"));
+	/// Something waiting on a FileInfo query
+	pub enum InfoWaiter {
+		/// A `steamworks.FileInfo` callback
+		Lua {
+			callback: LuaReference,
+			options: FileInfoOptions,
+		},
+		/// An item of a batched `steamworks.FileInfo` call
+		Batch(std::rc::Rc<InfoBatch>, usize),
+		/// `steamworks.DownloadPreview` needs the item's preview handle
+		Preview,
+	}
 
-				lua.push_number(info.m_rtimeCreated as _);
-				lua.set_field(-2, lua_string!("created"));
+	impl Steam {
+		pub fn file_info(&self, workshop_id: PublishedFileId, callback: LuaReference, options: FileInfoOptions) {
+			let language = options.language.clone();
+			self.request_file_info(workshop_id, language, InfoWaiter::Lua { callback, options });
+		}
 
-				lua.push_number(info.m_rtimeUpdated as _);
-				lua.set_field(-2, lua_string!("updated"));
+		/// Looks up several items with as few Steam queries as possible, calling back with a list of results in the same order
+		pub fn file_info_batch(&self, items: Vec<PublishedFileId>, callback: LuaReference, options: FileInfoOptions) {
+			let language = options.language.clone();
+			let batch = std::rc::Rc::new(InfoBatch {
+				callback: std::cell::Cell::new(Some(callback)),
+				results: RefCell::new(vec![None; items.len()]),
+				items: items.clone(),
+				options,
+			});
 
-				lua.push_number(info.m_nFileSize as _);
-				lua.set_field(-2, lua_string!("size"));
+			if items.is_empty() {
+				return batch.finish();
+			}
 
-				lua.push_binary_string(cstr_to_bytes!(info.m_rgchURL));
-				lua.set_field(-2, lua_string!("previewurl"));
+			let mut query = Vec::new();
+			for (i, workshop_id) in items.iter().enumerate() {
+				let key: InfoKey = (*workshop_id, language.clone());
+				if let Some(waiter) = self.wait_file_info(&key, InfoWaiter::Batch(batch.clone(), i)) {
+					self.file_info_pending.borrow_mut().insert(key, vec![waiter]);
+					query.push(*workshop_id);
+				}
+			}
 
-				lua.push_number(info.m_nPreviewFileSize as _);
-				lua.set_field(-2, lua_string!("previewsize"));
+			let ugc = self.server.ugc();
 
-				lua.push_number(info.m_unVotesUp as _);
-				lua.set_field(-2, lua_string!("up"));
+			for items in query.chunks(steamworks::RESULTS_PER_PAGE as usize) {
+				let items = items.to_vec();
+				let language = language.clone();
 
-				lua.push_number(info.m_unVotesDown as _);
-				lua.set_field(-2, lua_string!("down"));
+				let query = match ugc.query_items(items.clone()) {
+					Ok(query) => query,
+					Err(_) => {
+						for workshop_id in items {
+							self.file_info_failed((workshop_id, language.clone()), -1);
+						}
+						continue;
+					}
+				};
 
-				lua.push_number((info.m_unVotesUp as u64 + info.m_unVotesDown as u64) as _);
-				lua.set_field(-2, lua_string!("total"));
+				let query = match language {
+					Some(ref language) => query.language(language),
+					None => query
+				};
 
-				lua.push_number(info.m_flScore as _);
-				lua.set_field(-2, lua_string!("score"));
+				query.allow_cached_response(60).include_children(true).fetch(move |result| {
+					callbacks::pop();
+
+					crate::STEAM.with(|steam| {
+						for (i, workshop_id) in items.into_iter().enumerate() {
+							let key: InfoKey = (workshop_id, language.clone());
+							let info = match &result {
+								Ok(results) => check_details(workshop_id, results, i as u32),
+								Err(err) => Err(Into::<steamworks::sys::EResult>::into(*err) as i32)
+							};
+							match info {
+								Ok((details, children)) => steam.request_app_dependencies(key, details, children),
+								Err(error) => steam.file_info_complete(key, Err(error)),
+							}
+						}
+					});
+				});
 
-				lua.create_table(children.len() as _, 0);
-				for (i, child) in children.into_iter().enumerate() {
-					lua.push_string(&child.0.to_string());
-					lua.raw_seti(-2, (i + 1) as _);
-				}
-				lua.set_field(-2, lua_string!("children"));
+				callbacks::push();
+			}
+		}
 
-				break;
+		/// Answers the waiter from the cache or adds it to an in-flight query for the same item
+		///
+		/// Returns the waiter back if Steam has to be asked
+		fn wait_file_info(&self, key: &InfoKey, waiter: InfoWaiter) -> Option<InfoWaiter> {
+			let cached = self.file_info_cache.borrow_mut().get(key, &crate::config::get().file_info);
+			if let Some(info) = cached {
+				self.file_info_ready(key.0, waiter, &info);
+				return None;
 			}
 
-			lua.pcall_ignore(1, 0);
+			// Someone already asked Steam about this item, piggyback on their request
+			let mut pending = self.file_info_pending.borrow_mut();
+			match pending.get_mut(key) {
+				Some(waiters) => {
+					waiters.push(waiter);
+					None
+				},
+				None => Some(waiter)
+			}
 		}
-	}
 
-	impl Steam {
-		pub fn file_info(&self, workshop_id: PublishedFileId, callback: LuaReference) {
+		/// Asks Steam about an item in `language`, or in the item's default language
+		pub fn request_file_info(&self, workshop_id: PublishedFileId, language: Option<String>, waiter: InfoWaiter) {
+			let key: InfoKey = (workshop_id, language);
+
+			let waiter = match self.wait_file_info(&key, waiter) {
+				Some(waiter) => waiter,
+				None => return
+			};
+
 			let ugc = self.server.ugc();
 
 			#[cfg(debug_assertions)]
@@ -398,27 +1770,502 @@ use super::*;
 
 			let query = match ugc.query_item(workshop_id) {
 				Ok(query) => query,
-				res @ Err(_) => return self::callback(crate::lua(), callback, workshop_id, res.map_err(|_| None).map(|_| unreachable!()))
+				// Failed to create query
+				Err(_) => return self.file_info_ready(workshop_id, waiter, &Err(-1))
+			};
+
+			let query = match key.1 {
+				Some(ref language) => query.language(language),
+				None => query
 			};
 
+			self.file_info_pending.borrow_mut().insert(key.clone(), vec![waiter]);
+
 			query.allow_cached_response(60).include_children(true).fetch(move |result| {
 				#[cfg(debug_assertions)]
 				assert_eq!(thread_id, std::thread::current().id());
 
 				callbacks::pop();
-				self::callback(crate::lua(), callback, workshop_id, result.map_err(Some));
+
+				let info = file_info_result(workshop_id, result.map_err(Some));
+				crate::STEAM.with(|steam| match info {
+					Ok((details, children)) => steam.request_app_dependencies(key, details, children),
+					Err(error) => steam.file_info_complete(key, Err(error)),
+				});
+			});
+
+			callbacks::push();
+		}
+
+		/// Asks Steam which apps the item requires, which isn't part of the query results
+		///
+		/// They're the same in every language, so a cached result for the item is reused if there is one.
+		fn request_app_dependencies(&self, key: InfoKey, details: steamworks::QueryResult, children: Vec<PublishedFileId>) {
+			let cached = self.file_info_cache.borrow().app_dependencies(key.0, &crate::config::get().file_info);
+			if let Some(required_apps) = cached {
+				return self.file_info_complete(key, Ok((details, children, Some(required_apps))));
+			}
+
+			#[cfg(debug_assertions)]
+			let thread_id = std::thread::current().id();
+
+			self.server.ugc().app_dependencies(key.0, move |result| {
+				#[cfg(debug_assertions)]
+				assert_eq!(thread_id, std::thread::current().id());
+
+				callbacks::pop();
+
+				crate::STEAM.with(|steam| steam.file_info_complete(key, Ok((details, children, result.ok()))));
+			});
+
+			callbacks::push();
+		}
+
+		/// Caches the result and hands it to everyone waiting on the item
+		///
+		/// A result whose required apps couldn't be listed isn't cached, so the next call asks Steam again.
+		fn file_info_complete(&self, key: InfoKey, info: FileInfo) {
+			if !matches!(info, Ok((_, _, None))) {
+				self.file_info_cache.borrow_mut().insert(key.clone(), info.clone(), &crate::config::get().file_info);
+			}
+
+			let waiters = self.file_info_pending.borrow_mut().remove(&key).unwrap_or_default();
+			for waiter in waiters {
+				self.file_info_ready(key.0, waiter, &info);
+			}
+		}
+
+		/// Tells everyone waiting on the item that the query couldn't be made, without caching the error
+		fn file_info_failed(&self, key: InfoKey, error: i32) {
+			let waiters = self.file_info_pending.borrow_mut().remove(&key).unwrap_or_default();
+			for waiter in waiters {
+				self.file_info_ready(key.0, waiter, &Err(error));
+			}
+		}
+
+		fn file_info_ready(&self, workshop_id: PublishedFileId, waiter: InfoWaiter, info: &FileInfo) {
+			match waiter {
+				InfoWaiter::Lua { callback, options } => self::callback(crate::lua(), callback, workshop_id, info, &options),
+				InfoWaiter::Batch(batch, index) => batch.ready(index, info),
+				InfoWaiter::Preview => self.preview_info(workshop_id, info),
+			}
+		}
+
+		/// Forgets the cached `steamworks.FileInfo` result for an item
+		pub fn invalidate_file_info(&self, workshop_id: PublishedFileId) -> bool {
+			self.file_info_cache.borrow_mut().invalidate(workshop_id)
+		}
+	}
+}
+
+pub mod resource {
+	use super::*;
+
+	impl Steam {
+		/// Registers an item for client download with `resource.AddWorkshop`, expanding collections into their children
+		pub fn add_workshop(&self, workshop_id: PublishedFileId) {
+			self.add_workshop_items(vec![workshop_id], Vec::new());
+		}
+
+		fn add_workshop_items(&self, mut items: Vec<PublishedFileId>, mut expanded: Vec<PublishedFileId>) {
+			{
+				let registered = self.addworkshop.borrow();
+				items.retain(|workshop_id| !registered.contains(workshop_id) && !expanded.contains(workshop_id));
+			}
+
+			let ugc = self.server.ugc();
+
+			for items in items.chunks(steamworks::RESULTS_PER_PAGE as usize) {
+				let query = match ugc.query_items(items.to_vec()) {
+					Ok(query) => query,
+					Err(_) => {
+						// We can't tell whether these are collections, so just register them as they are
+						items.iter().for_each(|workshop_id| self.register_workshop(*workshop_id));
+						continue;
+					}
+				};
+
+				expanded.extend_from_slice(items);
+
+				let items = items.to_vec();
+				let expanded = expanded.clone();
+				query.allow_cached_response(60).include_children(true).fetch(move |result| {
+					callbacks::pop();
+
+					crate::STEAM.with(|steam| {
+						let results = match result {
+							Ok(results) => results,
+							Err(err) => {
+								eprintln!("[gmsv_workshop] Failed to query items for resource.AddWorkshop: {}", err);
+								items.iter().for_each(|workshop_id| steam.register_workshop(*workshop_id));
+								return;
+							}
+						};
+
+						let mut children = Vec::new();
+						for (i, details) in results.iter().enumerate() {
+							let details = match details {
+								Some(details) => details,
+								None => continue
+							};
+
+							let workshop_id = details.published_file_id;
+							if details.file_type == steamworks::FileType::Collection {
+								children.extend(results.get_children(i as _).unwrap_or_default());
+							} else {
+								steam.register_workshop(workshop_id);
+							}
+						}
+
+						if !children.is_empty() {
+							steam.add_workshop_items(children, expanded);
+						}
+					});
+				});
+
+				callbacks::push();
+			}
+		}
+
+		/// Calls `resource.AddWorkshop` for a single (non-collection) item and remembers that we did
+		pub fn register_workshop(&self, workshop_id: PublishedFileId) {
+			if !self.addworkshop.borrow_mut().insert(workshop_id) {
+				return;
+			}
+
+			unsafe {
+				let lua = crate::lua();
+				lua.get_global(lua_string!("resource"));
+				lua.get_field(-1, lua_string!("AddWorkshop"));
+				lua.push_string(&workshop_id.to_string());
+				lua.pcall_ignore(1, 0);
+				lua.pop();
+			}
+
+			println!("[gmsv_workshop] Registered {} with resource.AddWorkshop", workshop_id);
+		}
+	}
+}
+
+pub mod files {
+	use super::*;
+	use steamworks::{SteamError, UGCDownloadResult, UGCHandle};
+
+	/// Where `steamworks.Download` saves raw UGC files
+	pub const FILES_PATH: &str = "garrysmod/cache/srcds/ugc";
+
+	pub struct FileRequest {
+		pub uncompress: bool,
+		pub callback: LuaReference,
+	}
+
+	/// Path of a downloaded UGC file, relative to `garrysmod/`
+	fn file_path(handle: UGCHandle, uncompress: bool) -> String {
+		format!("cache/srcds/ugc/{}.{}", handle.0, if uncompress { "dat" } else { "bin" })
+	}
+
+	fn save(handle: UGCHandle, uncompress: bool, data: &[u8]) -> Result<String, std::io::Error> {
+		let path = file_path(handle, uncompress);
+
+		let decompressed;
+		let data = if uncompress {
+			decompressed = gmod_lzma::decompress(data).map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidData))?;
+			&decompressed
+		} else {
+			data
+		};
+
+		std::fs::create_dir_all(FILES_PATH)?;
+
+		let dest = PathBuf::from("garrysmod").join(&path);
+		let tmp = dest.with_extension("tmp");
+		let res = std::fs::write(&tmp, data).and_then(|_| std::fs::rename(&tmp, &dest));
+		if res.is_err() {
+			let _ = std::fs::remove_file(&tmp);
+		}
+
+		res.map(|_| path)
+	}
+
+	fn callback(lua: gmod::lua::State, callback: LuaReference, path: Result<String, String>) {
+		unsafe {
+			lua.from_reference(callback);
+			lua.dereference(callback);
+
+			match path {
+				Ok(path) => {
+					lua.push_string(&path);
+					lua.pcall_ignore(1, 0);
+				},
+				Err(error) => {
+					lua.push_nil();
+					lua.push_string(&error);
+					lua.pcall_ignore(2, 0);
+				}
+			}
+		}
+	}
+
+	impl Steam {
+		/// `steamworks.Download`: fetches an arbitrary UGC file handle, such as `FileInfo`'s `fileid` or `previewid`
+		pub fn download_file(&self, handle: UGCHandle, request: FileRequest) {
+			// UGC handles are never reused for different content, so anything already on disk is up to date
+			let path = file_path(handle, request.uncompress);
+			if std::path::Path::new("garrysmod").join(&path).is_file() {
+				return self::callback(crate::lua(), request.callback, Ok(path));
+			}
+
+			{
+				let mut files = self.files.borrow_mut();
+				if let Some(waiting) = files.get_mut(&handle) {
+					waiting.push(request);
+					return;
+				}
+				files.insert(handle, vec![request]);
+			}
+
+			let rs = match self.remote_storage() {
+				Ok(rs) => rs,
+				Err(error) => return self.file_downloaded(handle, Err(error))
+			};
+
+			rs.ugc_download(handle, 1, move |result| {
+				callbacks::pop();
+				crate::STEAM.with(|steam| steam.file_downloaded(handle, result));
+			});
+
+			callbacks::push();
+		}
+
+		fn file_downloaded(&self, handle: UGCHandle, result: Result<UGCDownloadResult, SteamError>) {
+			let requests = match self.files.borrow_mut().remove(&handle) {
+				Some(requests) => requests,
+				None => return
+			};
+
+			let data = result.and_then(|download| self.read_ugc(&download));
+			if let Err(ref error) = data {
+				eprintln!("[gmsv_workshop] Failed to download UGC file {}: {}", handle.0, error);
+			}
+
+			let lua = crate::lua();
+			let mut saved: [Option<Result<String, String>>; 2] = [None, None];
+			for request in requests {
+				let path = match data {
+					Ok(ref data) => saved[request.uncompress as usize].get_or_insert_with(|| {
+						save(handle, request.uncompress, data).map_err(|err| {
+							eprintln!("[gmsv_workshop] Failed to save UGC file {}: {}", handle.0, err);
+							err.to_string()
+						})
+					}).clone(),
+					Err(ref error) => Err(error.to_string())
+				};
+
+				self::callback(lua, request.callback, path);
+			}
+		}
+
+		/// The server's remote storage interface, which UGC files are downloaded through
+		pub fn remote_storage(&self) -> Result<steamworks::RemoteStorage<steamworks::ServerManager>, SteamError> {
+			self.server.remote_storage().ok_or_else(|| {
+				eprintln!("[gmsv_workshop] The remote storage interface is unavailable");
+				SteamError::Generic
+			})
+		}
+
+		/// Reads a finished UGC download into memory
+		pub fn read_ugc(&self, download: &UGCDownloadResult) -> Result<Vec<u8>, SteamError> {
+			let rs = self.remote_storage()?;
+
+			let mut data = vec![0u8; download.size as usize];
+			let mut read = 0;
+			while read < data.len() {
+				let n = rs.ugc_read(download.handle, &mut data[read..], read as u32, steamworks::UGCReadAction::ContinueReadingUntilFinished);
+				if n == 0 {
+					break;
+				}
+				read += n;
+			}
+
+			if read != data.len() {
+				return Err(SteamError::IOFailure);
+			}
+
+			Ok(data)
+		}
+	}
+}
+
+pub mod previews {
+	use super::*;
+	use crate::{image::ImageType, info_cache::FileInfo};
+	use std::path::Path;
+	use steamworks::UGCHandle;
+
+	/// Thumbnail cache, relative to `garrysmod/data/`
+	const PREVIEWS_PATH: &str = "gmsv_workshop/previews";
+
+	pub struct Preview {
+		/// Relative to `garrysmod/data/`
+		path: String,
+		image: ImageType,
+		dimensions: Option<(u32, u32)>,
+	}
+
+	fn preview_path(workshop_id: PublishedFileId, handle: UGCHandle, image: ImageType) -> String {
+		format!("{}/{}-{}.{}", PREVIEWS_PATH, workshop_id, handle.0, image.extension())
+	}
+
+	/// Finds a preview that was already downloaded for this handle
+	fn cached(workshop_id: PublishedFileId, handle: UGCHandle) -> Option<Preview> {
+		ImageType::ALL.into_iter().find_map(|image| {
+			let path = preview_path(workshop_id, handle, image);
+			let data = std::fs::read(Path::new("garrysmod/data").join(&path)).ok()?;
+			Some(Preview { path, image, dimensions: image.dimensions(&data) })
+		})
+	}
+
+	fn save(workshop_id: PublishedFileId, handle: UGCHandle, data: &[u8]) -> Result<Preview, String> {
+		let image = ImageType::detect(data).ok_or("unrecognised image format")?;
+		let path = preview_path(workshop_id, handle, image);
+
+		let dir = Path::new("garrysmod/data").join(PREVIEWS_PATH);
+		std::fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+
+		// The item's preview changed, so the old one isn't needed anymore
+		let prefix = format!("{}-", workshop_id);
+		if let Ok(entries) = dir.read_dir() {
+			for entry in entries.filter_map(|entry| entry.ok()) {
+				if entry.file_name().to_string_lossy().starts_with(&prefix) {
+					let _ = std::fs::remove_file(entry.path());
+				}
+			}
+		}
+
+		std::fs::write(Path::new("garrysmod/data").join(&path), data).map_err(|err| err.to_string())?;
+
+		Ok(Preview { path, image, dimensions: image.dimensions(data) })
+	}
+
+	impl Steam {
+		/// `steamworks.DownloadPreview`: fetches an item's preview image into the thumbnail cache
+		pub fn download_preview(&self, workshop_id: PublishedFileId, callback: LuaReference) {
+			{
+				let mut previews = self.previews.borrow_mut();
+				if let Some(waiting) = previews.get_mut(&workshop_id) {
+					waiting.push(callback);
+					return;
+				}
+				previews.insert(workshop_id, vec![callback]);
+			}
+
+			self.request_file_info(workshop_id, None, query::InfoWaiter::Preview);
+		}
+
+		pub(super) fn preview_info(&self, workshop_id: PublishedFileId, info: &FileInfo) {
+			let handle = match info {
+				Ok((details, ..)) => details.preview_file,
+				Err(error) => return self.preview_ready(workshop_id, Err(format!("FileInfo failed with error {}", error)))
+			};
+			let handle = match handle {
+				Some(handle) => handle,
+				None => return self.preview_ready(workshop_id, Err("item has no preview".to_owned()))
+			};
+
+			if let Some(preview) = cached(workshop_id, handle) {
+				return self.preview_ready(workshop_id, Ok(preview));
+			}
+
+			let rs = match self.remote_storage() {
+				Ok(rs) => rs,
+				Err(error) => return self.preview_ready(workshop_id, Err(error.to_string()))
+			};
+
+			rs.ugc_download(handle, 1, move |result| {
+				callbacks::pop();
+
+				crate::STEAM.with(|steam| {
+					let preview = result
+						.and_then(|download| steam.read_ugc(&download))
+						.map_err(|err| err.to_string())
+						.and_then(|data| save(workshop_id, handle, &data));
+
+					steam.preview_ready(workshop_id, preview);
+				});
 			});
 
 			callbacks::push();
 		}
+
+		fn preview_ready(&self, workshop_id: PublishedFileId, preview: Result<Preview, String>) {
+			let callbacks = self.previews.borrow_mut().remove(&workshop_id).unwrap_or_default();
+
+			if let Err(ref error) = preview {
+				eprintln!("[gmsv_workshop] Failed to download preview for {}: {}", workshop_id, error);
+			}
+
+			let lua = crate::lua();
+			for callback in callbacks {
+				unsafe {
+					lua.from_reference(callback);
+					lua.dereference(callback);
+
+					match preview {
+						Ok(ref preview) => {
+							lua.push_string(&preview.path);
+
+							lua.new_table();
+
+							lua.push_string(preview.image.extension());
+							lua.set_field(-2, lua_string!("type"));
+
+							if let Some((width, height)) = preview.dimensions {
+								lua.push_integer(width as _);
+								lua.set_field(-2, lua_string!("width"));
+
+								lua.push_integer(height as _);
+								lua.set_field(-2, lua_string!("height"));
+							}
+
+							lua.pcall_ignore(2, 0);
+						},
+
+						Err(ref error) => {
+							lua.push_nil();
+							lua.push_string(error);
+							lua.pcall_ignore(2, 0);
+						}
+					}
+				}
+			}
+		}
 	}
 }
 
 pub struct Steam {
 	pub server: ManuallyDrop<steamworks::Server>,
 	pub callbacks: ManuallyDrop<steamworks::SingleClient<steamworks::ServerManager>>,
-	pub pending: RefCell<HashMap<PublishedFileId, LuaReference>>,
-	pub queued: RefCell<HashMap<PublishedFileId, Option<LuaReference>>>,
+	pub pending: RefCell<HashMap<PublishedFileId, downloads::DownloadRequest>>,
+	pub queued: RefCell<HashMap<PublishedFileId, downloads::DownloadRequest>>,
+	pub scheduled: RefCell<HashMap<PublishedFileId, downloads::DownloadRequest>>,
+	pub retrying: RefCell<HashMap<PublishedFileId, (std::time::Instant, downloads::DownloadRequest)>>,
+	pub preflight: RefCell<HashMap<PublishedFileId, downloads::DownloadRequest>>,
+	/// Legacy items being downloaded through ISteamRemoteStorage
+	pub legacy: RefCell<HashMap<PublishedFileId, downloads::DownloadRequest>>,
+	pub addworkshop: RefCell<BTreeSet<PublishedFileId>>,
+	pub quota_usage: RefCell<crate::quota::QuotaUsage>,
+	pub mounts: RefCell<crate::mounts::MountTable>,
+	/// Mirrored collection and its children as of the last sync
+	pub mirror: RefCell<Option<crate::mirror::MirrorState>>,
+	/// Items served this session, and the `m_rtimeUpdated` of the version we have
+	pub watched: RefCell<std::collections::BTreeMap<PublishedFileId, u32>>,
+	pub file_info_cache: RefCell<crate::info_cache::InfoCache>,
+	/// Callbacks waiting on an in-flight `steamworks.FileInfo` query
+	pub file_info_pending: RefCell<HashMap<crate::info_cache::InfoKey, Vec<query::InfoWaiter>>>,
+	/// Callbacks waiting on an in-flight `steamworks.Download`
+	pub files: RefCell<HashMap<steamworks::UGCHandle, Vec<files::FileRequest>>>,
+	/// Callbacks waiting on `steamworks.DownloadPreview`
+	pub previews: RefCell<HashMap<PublishedFileId, Vec<LuaReference>>>,
 }
 impl Steam {
 	pub fn init() -> Steam {
@@ -431,8 +2278,71 @@ impl Steam {
 		Steam {
 			pending: Default::default(),
 			queued: Default::default(),
+			scheduled: Default::default(),
+			retrying: Default::default(),
+			preflight: Default::default(),
+			legacy: Default::default(),
+			addworkshop: Default::default(),
+			quota_usage: Default::default(),
+			mounts: Default::default(),
+			mirror: Default::default(),
+			watched: Default::default(),
+			file_info_cache: Default::default(),
+			file_info_pending: Default::default(),
+			files: Default::default(),
+			previews: Default::default(),
 			server: ManuallyDrop::new(server),
 			callbacks: ManuallyDrop::new(callbacks)
 		}
 	}
+
+	/// Tears down everything tied to the closing Lua state
+	pub fn close(&self, lua: gmod::lua::State) {
+		self.persist_downloads(lua);
+
+		// Pending call results capture Lua references from this state, so they must never run
+		self.callbacks.clear_call_results();
+		callbacks::reset();
+
+		self.addworkshop.borrow_mut().clear();
+		self.mirror.take();
+		self.watched.borrow_mut().clear();
+
+		for waiter in self.file_info_pending.take().into_values().flatten() {
+			match waiter {
+				query::InfoWaiter::Lua { callback, .. } => unsafe { lua.dereference(callback) },
+				query::InfoWaiter::Batch(batch, _) => unsafe { batch.abandon(lua) },
+				query::InfoWaiter::Preview => {}
+			}
+		}
+		for request in self.files.take().into_values().flatten() {
+			unsafe { lua.dereference(request.callback) };
+		}
+		for callback in self.previews.take().into_values().flatten() {
+			unsafe { lua.dereference(callback) };
+		}
+
+		unsafe {
+			lua.get_global(lua_string!("hook"));
+			lua.get_field(-1, lua_string!("Remove"));
+			lua.push_string("Think");
+			lua.push_string("gmsv_workshop_queued");
+			lua.pcall_ignore(2, 0);
+			lua.pop();
+
+			lua.get_global(lua_string!("timer"));
+			lua.get_field(-1, lua_string!("Remove"));
+			lua.push_string("gmsv_workshop");
+			lua.pcall_ignore(1, 0);
+			lua.pop();
+
+			for timer in ["gmsv_workshop_mirror", "gmsv_workshop_mirror_retry", "gmsv_workshop_watch"] {
+				lua.get_global(lua_string!("timer"));
+				lua.get_field(-1, lua_string!("Remove"));
+				lua.push_string(timer);
+				lua.pcall_ignore(1, 0);
+				lua.pop();
+			}
+		}
+	}
 }
@@ -306,6 +306,11 @@ where
 			}
 		}
 	}
+
+	/// Drops all pending call results without running them
+	pub fn clear_call_results(&self) {
+		self.inner.callbacks.lock().unwrap().call_results.clear();
+	}
 }
 
 impl<Manager> Client<Manager> {
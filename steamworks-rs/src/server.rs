@@ -325,6 +325,30 @@ impl Server {
         }
     }
 
+    /// Returns an accessor to the steam remote storage interface
+    ///
+    /// Game servers have no cloud storage of their own, so only the UGC
+    /// functions (`ugc_download` and friends) are useful here.
+    ///
+    /// Returns `None` if the game server doesn't provide the interface.
+    pub fn remote_storage(&self) -> Option<RemoteStorage<ServerManager>> {
+        unsafe {
+            let rs = sys::SteamInternal_FindOrCreateGameServerInterface(
+                sys::SteamGameServer_GetHSteamUser(),
+                sys::STEAMREMOTESTORAGE_INTERFACE_VERSION.as_ptr() as *const _,
+            ) as *mut sys::ISteamRemoteStorage;
+            let util = sys::SteamAPI_SteamGameServerUtils_v010();
+            if rs.is_null() || util.is_null() {
+                return None;
+            }
+            Some(RemoteStorage {
+                rs,
+                util,
+                inner: self.inner.clone(),
+            })
+        }
+    }
+
     /* TODO: Buggy currently?
     /// Returns an accessor to the steam apps interface
     pub fn apps(&self) -> Apps<ServerManager> {
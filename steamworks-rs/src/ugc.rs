@@ -123,24 +123,75 @@ impl Into<sys::EWorkshopFileType> for FileType {
 }
 impl From<sys::EWorkshopFileType> for FileType {
 	fn from(file_type: sys::EWorkshopFileType) -> FileType {
-		match file_type {
-			sys::EWorkshopFileType::k_EWorkshopFileTypeCommunity => FileType::Community,
-			sys::EWorkshopFileType::k_EWorkshopFileTypeMicrotransaction => FileType::Microtransaction,
-			sys::EWorkshopFileType::k_EWorkshopFileTypeCollection => FileType::Collection,
-			sys::EWorkshopFileType::k_EWorkshopFileTypeArt => FileType::Art,
-			sys::EWorkshopFileType::k_EWorkshopFileTypeVideo => FileType::Video,
-			sys::EWorkshopFileType::k_EWorkshopFileTypeScreenshot => FileType::Screenshot,
-			sys::EWorkshopFileType::k_EWorkshopFileTypeGame => FileType::Game,
-			sys::EWorkshopFileType::k_EWorkshopFileTypeSoftware => FileType::Software,
-			sys::EWorkshopFileType::k_EWorkshopFileTypeConcept => FileType::Concept,
-			sys::EWorkshopFileType::k_EWorkshopFileTypeWebGuide => FileType::WebGuide,
-			sys::EWorkshopFileType::k_EWorkshopFileTypeIntegratedGuide => FileType::IntegratedGuide,
-			sys::EWorkshopFileType::k_EWorkshopFileTypeMerch => FileType::Merch,
-			sys::EWorkshopFileType::k_EWorkshopFileTypeControllerBinding => FileType::ControllerBinding,
-			sys::EWorkshopFileType::k_EWorkshopFileTypeSteamworksAccessInvite => FileType::SteamworksAccessInvite,
-			sys::EWorkshopFileType::k_EWorkshopFileTypeSteamVideo => FileType::SteamVideo,
-			sys::EWorkshopFileType::k_EWorkshopFileTypeGameManagedItem => FileType::GameManagedItem,
-			_ => unreachable!()
+		FileType::from_raw(file_type as u32)
+	}
+}
+impl FileType {
+	/// Maps a raw `EWorkshopFileType`. Values these bindings don't know about are treated as
+	/// `Community`, the ordinary item type.
+	fn from_raw(file_type: u32) -> FileType {
+		[
+			FileType::Community,
+			FileType::Microtransaction,
+			FileType::Collection,
+			FileType::Art,
+			FileType::Video,
+			FileType::Screenshot,
+			FileType::Game,
+			FileType::Software,
+			FileType::Concept,
+			FileType::WebGuide,
+			FileType::IntegratedGuide,
+			FileType::Merch,
+			FileType::ControllerBinding,
+			FileType::SteamworksAccessInvite,
+			FileType::SteamVideo,
+			FileType::GameManagedItem,
+		]
+		.iter()
+		.copied()
+		.find(|known| Into::<sys::EWorkshopFileType>::into(*known) as u32 == file_type)
+		.unwrap_or(FileType::Community)
+	}
+}
+
+/// Who can see a workshop item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublishedFileVisibility {
+	Public,
+	FriendsOnly,
+	Private,
+	/// Visible to anyone with the link, but not shown in searches
+	Unlisted,
+}
+impl From<PublishedFileVisibility> for sys::ERemoteStoragePublishedFileVisibility {
+	fn from(visibility: PublishedFileVisibility) -> sys::ERemoteStoragePublishedFileVisibility {
+		match visibility {
+			PublishedFileVisibility::Public => sys::ERemoteStoragePublishedFileVisibility::k_ERemoteStoragePublishedFileVisibilityPublic,
+			PublishedFileVisibility::FriendsOnly => sys::ERemoteStoragePublishedFileVisibility::k_ERemoteStoragePublishedFileVisibilityFriendsOnly,
+			PublishedFileVisibility::Private => sys::ERemoteStoragePublishedFileVisibility::k_ERemoteStoragePublishedFileVisibilityPrivate,
+			PublishedFileVisibility::Unlisted => sys::ERemoteStoragePublishedFileVisibility::k_ERemoteStoragePublishedFileVisibilityUnlisted,
+		}
+	}
+}
+impl From<sys::ERemoteStoragePublishedFileVisibility> for PublishedFileVisibility {
+	fn from(visibility: sys::ERemoteStoragePublishedFileVisibility) -> PublishedFileVisibility {
+		PublishedFileVisibility::from_raw(visibility as u32)
+	}
+}
+impl PublishedFileVisibility {
+	/// Maps a raw `ERemoteStoragePublishedFileVisibility`. Values these bindings don't know
+	/// about are treated as `Private`, the most restrictive visibility.
+	fn from_raw(visibility: u32) -> PublishedFileVisibility {
+		const PUBLIC: u32 = sys::ERemoteStoragePublishedFileVisibility::k_ERemoteStoragePublishedFileVisibilityPublic as u32;
+		const FRIENDS_ONLY: u32 = sys::ERemoteStoragePublishedFileVisibility::k_ERemoteStoragePublishedFileVisibilityFriendsOnly as u32;
+		const UNLISTED: u32 = sys::ERemoteStoragePublishedFileVisibility::k_ERemoteStoragePublishedFileVisibilityUnlisted as u32;
+
+		match visibility {
+			PUBLIC => PublishedFileVisibility::Public,
+			FRIENDS_ONLY => PublishedFileVisibility::FriendsOnly,
+			UNLISTED => PublishedFileVisibility::Unlisted,
+			_ => PublishedFileVisibility::Private,
 		}
 	}
 }
@@ -198,6 +249,58 @@ impl Into<sys::EUserUGCListSortOrder> for UserListOrder {
 	}
 }
 
+/// Sorting for queries over all items
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UGCQueryType {
+	RankedByVote,
+	RankedByPublicationDate,
+	AcceptedForGameRankedByAcceptanceDate,
+	RankedByTrend,
+	FavoritedByFriendsRankedByPublicationDate,
+	CreatedByFriendsRankedByPublicationDate,
+	RankedByNumTimesReported,
+	CreatedByFollowedUsersRankedByPublicationDate,
+	NotYetRated,
+	RankedByTotalVotesAsc,
+	RankedByVotesUp,
+	RankedByTextSearch,
+	RankedByTotalUniqueSubscriptions,
+	RankedByPlaytimeTrend,
+	RankedByTotalPlaytime,
+	RankedByAveragePlaytimeTrend,
+	RankedByLifetimeAveragePlaytime,
+	RankedByPlaytimeSessionsTrend,
+	RankedByLifetimePlaytimeSessions,
+	RankedByLastUpdatedDate,
+}
+
+impl From<UGCQueryType> for sys::EUGCQuery {
+	fn from(query_type: UGCQueryType) -> sys::EUGCQuery {
+		match query_type {
+			UGCQueryType::RankedByVote => sys::EUGCQuery::k_EUGCQuery_RankedByVote,
+			UGCQueryType::RankedByPublicationDate => sys::EUGCQuery::k_EUGCQuery_RankedByPublicationDate,
+			UGCQueryType::AcceptedForGameRankedByAcceptanceDate => sys::EUGCQuery::k_EUGCQuery_AcceptedForGameRankedByAcceptanceDate,
+			UGCQueryType::RankedByTrend => sys::EUGCQuery::k_EUGCQuery_RankedByTrend,
+			UGCQueryType::FavoritedByFriendsRankedByPublicationDate => sys::EUGCQuery::k_EUGCQuery_FavoritedByFriendsRankedByPublicationDate,
+			UGCQueryType::CreatedByFriendsRankedByPublicationDate => sys::EUGCQuery::k_EUGCQuery_CreatedByFriendsRankedByPublicationDate,
+			UGCQueryType::RankedByNumTimesReported => sys::EUGCQuery::k_EUGCQuery_RankedByNumTimesReported,
+			UGCQueryType::CreatedByFollowedUsersRankedByPublicationDate => sys::EUGCQuery::k_EUGCQuery_CreatedByFollowedUsersRankedByPublicationDate,
+			UGCQueryType::NotYetRated => sys::EUGCQuery::k_EUGCQuery_NotYetRated,
+			UGCQueryType::RankedByTotalVotesAsc => sys::EUGCQuery::k_EUGCQuery_RankedByTotalVotesAsc,
+			UGCQueryType::RankedByVotesUp => sys::EUGCQuery::k_EUGCQuery_RankedByVotesUp,
+			UGCQueryType::RankedByTextSearch => sys::EUGCQuery::k_EUGCQuery_RankedByTextSearch,
+			UGCQueryType::RankedByTotalUniqueSubscriptions => sys::EUGCQuery::k_EUGCQuery_RankedByTotalUniqueSubscriptions,
+			UGCQueryType::RankedByPlaytimeTrend => sys::EUGCQuery::k_EUGCQuery_RankedByPlaytimeTrend,
+			UGCQueryType::RankedByTotalPlaytime => sys::EUGCQuery::k_EUGCQuery_RankedByTotalPlaytime,
+			UGCQueryType::RankedByAveragePlaytimeTrend => sys::EUGCQuery::k_EUGCQuery_RankedByAveragePlaytimeTrend,
+			UGCQueryType::RankedByLifetimeAveragePlaytime => sys::EUGCQuery::k_EUGCQuery_RankedByLifetimeAveragePlaytime,
+			UGCQueryType::RankedByPlaytimeSessionsTrend => sys::EUGCQuery::k_EUGCQuery_RankedByPlaytimeSessionsTrend,
+			UGCQueryType::RankedByLifetimePlaytimeSessions => sys::EUGCQuery::k_EUGCQuery_RankedByLifetimePlaytimeSessions,
+			UGCQueryType::RankedByLastUpdatedDate => sys::EUGCQuery::k_EUGCQuery_RankedByLastUpdatedDate,
+		}
+	}
+}
+
 /// Available user-specific lists.
 /// Certain ones are only available to the currently logged in user.
 #[derive(Debug,Clone,Copy,PartialEq,Eq)]
@@ -289,6 +392,111 @@ impl Into<sys::EItemStatistic> for UGCStatisticType {
 	}
 }
 
+/// The kind of an item's additional preview.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum ItemPreviewType {
+	/// An image file.
+	Image,
+	/// A YouTube video, identified by its video ID.
+	YouTubeVideo,
+	/// A Sketchfab model, identified by its model ID.
+	Sketchfab,
+	/// An environment map laid out as a horizontal cross.
+	EnvironmentMapHorizontalCross,
+	/// An environment map laid out as latitude/longitude.
+	EnvironmentMapLatLong,
+	/// A type this version of the SDK doesn't know about.
+	Other(u32),
+}
+impl ItemPreviewType {
+	fn from_raw(raw: u32) -> ItemPreviewType {
+		[
+			ItemPreviewType::Image,
+			ItemPreviewType::YouTubeVideo,
+			ItemPreviewType::Sketchfab,
+			ItemPreviewType::EnvironmentMapHorizontalCross,
+			ItemPreviewType::EnvironmentMapLatLong,
+		]
+		.iter()
+		.copied()
+		.find(|known| known.to_raw() == raw)
+		.unwrap_or(ItemPreviewType::Other(raw))
+	}
+
+	fn to_raw(self) -> u32 {
+		match self {
+			ItemPreviewType::Image => sys::EItemPreviewType::k_EItemPreviewType_Image as u32,
+			ItemPreviewType::YouTubeVideo => sys::EItemPreviewType::k_EItemPreviewType_YouTubeVideo as u32,
+			ItemPreviewType::Sketchfab => sys::EItemPreviewType::k_EItemPreviewType_Sketchfab as u32,
+			ItemPreviewType::EnvironmentMapHorizontalCross => sys::EItemPreviewType::k_EItemPreviewType_EnvironmentMap_HorizontalCross as u32,
+			ItemPreviewType::EnvironmentMapLatLong => sys::EItemPreviewType::k_EItemPreviewType_EnvironmentMap_LatLong as u32,
+			ItemPreviewType::Other(other) => other,
+		}
+	}
+}
+
+/// An additional image or video attached to an item, besides its main preview.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct AdditionalPreview {
+	/// The URL of the image, or the video ID for YouTube and Sketchfab previews.
+	pub url_or_video_id: String,
+	/// The name of the file that was uploaded, if any.
+	pub original_file_name: Option<String>,
+	pub preview_type: ItemPreviewType,
+}
+
+/// Content warnings an item's author has flagged it with.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum ContentDescriptor {
+	NudityOrSexualContent,
+	FrequentViolenceOrGore,
+	AdultOnlySexualContent,
+	GratuitousSexualContent,
+	AnyMatureContent,
+	/// A descriptor this version of the SDK doesn't know about.
+	Other(u32),
+}
+impl ContentDescriptor {
+	fn from_raw(raw: u32) -> ContentDescriptor {
+		[
+			ContentDescriptor::NudityOrSexualContent,
+			ContentDescriptor::FrequentViolenceOrGore,
+			ContentDescriptor::AdultOnlySexualContent,
+			ContentDescriptor::GratuitousSexualContent,
+			ContentDescriptor::AnyMatureContent,
+		]
+		.iter()
+		.copied()
+		.find(|known| known.to_raw() == raw)
+		.unwrap_or(ContentDescriptor::Other(raw))
+	}
+
+	fn to_raw(self) -> u32 {
+		match self {
+			ContentDescriptor::NudityOrSexualContent => sys::EUGCContentDescriptorID::k_EUGCContentDescriptor_NudityOrSexualContent as u32,
+			ContentDescriptor::FrequentViolenceOrGore => sys::EUGCContentDescriptorID::k_EUGCContentDescriptor_FrequentViolenceOrGore as u32,
+			ContentDescriptor::AdultOnlySexualContent => sys::EUGCContentDescriptorID::k_EUGCContentDescriptor_AdultOnlySexualContent as u32,
+			ContentDescriptor::GratuitousSexualContent => sys::EUGCContentDescriptorID::k_EUGCContentDescriptor_GratuitousSexualContent as u32,
+			ContentDescriptor::AnyMatureContent => sys::EUGCContentDescriptorID::k_EUGCContentDescriptor_AnyMatureContent as u32,
+			ContentDescriptor::Other(other) => other,
+		}
+	}
+}
+
+/// The apps an item requires, from `app_dependencies`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppDependencies {
+	pub app_ids: Vec<AppId>,
+	/// How many apps the item requires in total, which may be more than Steam returned.
+	pub total: u32,
+}
+impl AppDependencies {
+	/// Whether Steam left some of the required apps out of `app_ids`.
+	pub fn truncated(&self) -> bool {
+		self.total as usize > self.app_ids.len()
+	}
+}
+
 bitflags! {
 	pub struct ItemState: u32 {
 		const NONE = 0;
@@ -376,6 +584,7 @@ impl <Manager> UGC<Manager> {
 				inner: self.inner.clone(),
 
 				handle,
+				changes: UpdateChanges::empty(),
 			}
 		}
 	}
@@ -476,6 +685,35 @@ impl <Manager> UGC<Manager> {
 		}
 	}
 
+	/// Queries all workshop items matching `item_type` and `appids`, sorted by `query_type`
+	pub fn query_all(&self,
+		query_type: UGCQueryType,
+		item_type: UGCType,
+		appids: AppIDs,
+		page: u32
+	) -> Result<AllQuery<Manager>, CreateQueryError> {
+		let res = unsafe {
+			sys::SteamAPI_ISteamUGC_CreateQueryAllUGCRequestPage(
+				self.ugc,
+				query_type.into(),
+				item_type.into(),
+				appids.creator_app_id().unwrap_or(AppId(0)).0,
+				appids.consumer_app_id().unwrap_or(AppId(0)).0,
+				page,
+			)
+		};
+
+		if res == UGCQueryHandleInvalid {
+			return Err(CreateQueryError);
+		}
+
+		Ok(AllQuery {
+			ugc: self.ugc,
+			inner: Arc::clone(&self.inner),
+			handle: Some(res),
+		})
+	}
+
 	/// Queries a list of workshop itmes, related to a user in some way (Ex. user's subscriptions, favorites, upvoted, ...)
 	pub fn query_user(&self,
 		account: AccountId,
@@ -572,6 +810,112 @@ impl <Manager> UGC<Manager> {
 			});
 		}
 	}
+
+	/// Makes `child` a dependency of `parent`, so subscribing to `parent` also subscribes to `child`.
+	pub fn add_dependency<F>(&self, parent: PublishedFileId, child: PublishedFileId, cb: F)
+		where F: FnOnce(Result<(), SteamError>) + 'static + Send
+	{
+		unsafe {
+			let api_call = sys::SteamAPI_ISteamUGC_AddDependency(self.ugc, parent.0, child.0);
+			register_call_result::<sys::AddUGCDependencyResult_t, _, _>(
+				&self.inner, api_call, CALLBACK_BASE_ID + 12,
+				move |v, io_error| {
+					cb(if io_error {
+						Err(SteamError::IOFailure)
+					} else if v.m_eResult != sys::EResult::k_EResultOK {
+						Err(v.m_eResult.into())
+					} else {
+						Ok(())
+					})
+			});
+		}
+	}
+
+	/// Removes `child` from the dependencies of `parent`.
+	pub fn remove_dependency<F>(&self, parent: PublishedFileId, child: PublishedFileId, cb: F)
+		where F: FnOnce(Result<(), SteamError>) + 'static + Send
+	{
+		unsafe {
+			let api_call = sys::SteamAPI_ISteamUGC_RemoveDependency(self.ugc, parent.0, child.0);
+			register_call_result::<sys::RemoveUGCDependencyResult_t, _, _>(
+				&self.inner, api_call, CALLBACK_BASE_ID + 13,
+				move |v, io_error| {
+					cb(if io_error {
+						Err(SteamError::IOFailure)
+					} else if v.m_eResult != sys::EResult::k_EResultOK {
+						Err(v.m_eResult.into())
+					} else {
+						Ok(())
+					})
+			});
+		}
+	}
+
+	/// Marks the item as requiring `app_id`, such as a game whose content it uses.
+	pub fn add_app_dependency<F>(&self, published_file_id: PublishedFileId, app_id: AppId, cb: F)
+		where F: FnOnce(Result<(), SteamError>) + 'static + Send
+	{
+		unsafe {
+			let api_call = sys::SteamAPI_ISteamUGC_AddAppDependency(self.ugc, published_file_id.0, app_id.0);
+			register_call_result::<sys::AddAppDependencyResult_t, _, _>(
+				&self.inner, api_call, CALLBACK_BASE_ID + 14,
+				move |v, io_error| {
+					cb(if io_error {
+						Err(SteamError::IOFailure)
+					} else if v.m_eResult != sys::EResult::k_EResultOK {
+						Err(v.m_eResult.into())
+					} else {
+						Ok(())
+					})
+			});
+		}
+	}
+
+	/// Removes `app_id` from the apps the item requires.
+	pub fn remove_app_dependency<F>(&self, published_file_id: PublishedFileId, app_id: AppId, cb: F)
+		where F: FnOnce(Result<(), SteamError>) + 'static + Send
+	{
+		unsafe {
+			let api_call = sys::SteamAPI_ISteamUGC_RemoveAppDependency(self.ugc, published_file_id.0, app_id.0);
+			register_call_result::<sys::RemoveAppDependencyResult_t, _, _>(
+				&self.inner, api_call, CALLBACK_BASE_ID + 15,
+				move |v, io_error| {
+					cb(if io_error {
+						Err(SteamError::IOFailure)
+					} else if v.m_eResult != sys::EResult::k_EResultOK {
+						Err(v.m_eResult.into())
+					} else {
+						Ok(())
+					})
+			});
+		}
+	}
+
+	/// Gets the apps the item requires.
+	///
+	/// Steam returns at most 32 apps per result, see `AppDependencies::truncated`.
+	pub fn app_dependencies<F>(&self, published_file_id: PublishedFileId, cb: F)
+		where F: FnOnce(Result<AppDependencies, SteamError>) + 'static + Send
+	{
+		unsafe {
+			let api_call = sys::SteamAPI_ISteamUGC_GetAppDependencies(self.ugc, published_file_id.0);
+			register_call_result::<sys::GetAppDependenciesResult_t, _, _>(
+				&self.inner, api_call, CALLBACK_BASE_ID + 16,
+				move |v, io_error| {
+					cb(if io_error {
+						Err(SteamError::IOFailure)
+					} else if v.m_eResult != sys::EResult::k_EResultOK {
+						Err(v.m_eResult.into())
+					} else {
+						let count = (v.m_nNumAppDependencies as usize).min(v.m_rgAppIDs.len());
+						Ok(AppDependencies {
+							app_ids: v.m_rgAppIDs[..count].iter().map(|app_id| AppId(*app_id)).collect(),
+							total: v.m_nTotalNumAppDependencies,
+						})
+					})
+			});
+		}
+	}
 }
 
 impl UGC<ServerManager> {
@@ -596,52 +940,231 @@ pub struct UpdateHandle<Manager> {
 	inner: Arc<Inner<Manager>>,
 
 	handle: sys::UGCUpdateHandle_t,
+	changes: UpdateChanges,
+}
+
+/// Prototypes of the SDK functions that take `EItemPreviewType` or `EUGCContentDescriptorID`,
+/// with the enum as a plain integer. They're C enums, so this is the same ABI, and it lets values
+/// newer than the bindings (`Other`) through without building an invalid Rust enum.
+#[allow(non_snake_case)]
+mod raw_enums {
+	use super::*;
+
+	extern "C" {
+		#[link_name = "SteamAPI_ISteamUGC_AddItemPreviewFile"]
+		pub fn AddItemPreviewFile(ugc: *mut sys::ISteamUGC, handle: sys::UGCUpdateHandle_t, preview_file: *const c_char, preview_type: u32) -> bool;
+		#[link_name = "SteamAPI_ISteamUGC_AddContentDescriptor"]
+		pub fn AddContentDescriptor(ugc: *mut sys::ISteamUGC, handle: sys::UGCUpdateHandle_t, descriptor: u32) -> bool;
+		#[link_name = "SteamAPI_ISteamUGC_RemoveContentDescriptor"]
+		pub fn RemoveContentDescriptor(ugc: *mut sys::ISteamUGC, handle: sys::UGCUpdateHandle_t, descriptor: u32) -> bool;
+	}
 }
 
 impl <Manager> UpdateHandle<Manager> {
 	#[must_use]
-	pub fn title(self, title: &str) -> Self {
+	pub fn title(mut self, title: &str) -> Self {
 		unsafe {
 			let title = CString::new(title).unwrap();
 			assert!(sys::SteamAPI_ISteamUGC_SetItemTitle(self.ugc, self.handle, title.as_ptr()));
 		}
+		self.changes.insert(UpdateChanges::TITLE);
 		self
 	}
 
 	#[must_use]
-	pub fn description(self, description: &str) -> Self {
+	pub fn description(mut self, description: &str) -> Self {
 		unsafe {
 			let description = CString::new(description).unwrap();
 			assert!(sys::SteamAPI_ISteamUGC_SetItemDescription(self.ugc, self.handle, description.as_ptr()));
 		}
+		self.changes.insert(UpdateChanges::DESCRIPTION);
 		self
 	}
 
 	#[must_use]
-	pub fn preview_path(self, path: &Path) -> Self {
+	pub fn preview_path(mut self, path: &Path) -> Self {
 		unsafe {
 			let path = path.canonicalize().unwrap();
 			let preview_path = CString::new(&*path.to_string_lossy()).unwrap();
 			assert!(sys::SteamAPI_ISteamUGC_SetItemPreview(self.ugc, self.handle, preview_path.as_ptr()));
 		}
+		self.changes.insert(UpdateChanges::PREVIEW);
 		self
 	}
 
 	#[must_use]
-	pub fn content_path(self, path: &Path) -> Self {
+	pub fn content_path(mut self, path: &Path) -> Self {
 		unsafe {
 			let path = path.canonicalize().unwrap();
 			let content_path = CString::new(&*path.to_string_lossy()).unwrap();
 			assert!(sys::SteamAPI_ISteamUGC_SetItemContent(self.ugc, self.handle, content_path.as_ptr()));
 		}
+		self.changes.insert(UpdateChanges::CONTENT);
 		self
 	}
 
-	pub fn tags<S: AsRef<str>>(self, tags: Vec<S>) -> Self {
+	pub fn tags<S: AsRef<str>>(mut self, tags: Vec<S>) -> Self {
 		unsafe {
 			let mut tags = SteamParamStringArray::new(&tags);
 			assert!(sys::SteamAPI_ISteamUGC_SetItemTags(self.ugc, self.handle, &tags.as_raw()));
 		}
+		self.changes.insert(UpdateChanges::TAGS);
+		self
+	}
+
+	/// Sets the language that the title and description are in.
+	///
+	/// Defaults to "english"
+	#[must_use]
+	pub fn language(mut self, language: &str) -> Self {
+		unsafe {
+			let language = CString::new(language).unwrap();
+			assert!(sys::SteamAPI_ISteamUGC_SetItemUpdateLanguage(self.ugc, self.handle, language.as_ptr()));
+		}
+		self.changes.insert(UpdateChanges::LANGUAGE);
+		self
+	}
+
+	/// Sets the developer metadata of the item.
+	///
+	/// Panics if `metadata` is longer than 5000 bytes.
+	#[must_use]
+	pub fn metadata(mut self, metadata: &str) -> Self {
+		assert!(metadata.len() <= sys::k_cchDeveloperMetadataMax as usize, "metadata is longer than {} bytes", sys::k_cchDeveloperMetadataMax);
+		unsafe {
+			let metadata = CString::new(metadata).unwrap();
+			assert!(sys::SteamAPI_ISteamUGC_SetItemMetadata(self.ugc, self.handle, metadata.as_ptr()));
+		}
+		self.changes.insert(UpdateChanges::METADATA);
+		self
+	}
+
+	/// Sets who can see the item.
+	#[must_use]
+	pub fn visibility(mut self, visibility: PublishedFileVisibility) -> Self {
+		unsafe {
+			assert!(sys::SteamAPI_ISteamUGC_SetItemVisibility(self.ugc, self.handle, visibility.into()));
+		}
+		self.changes.insert(UpdateChanges::VISIBILITY);
+		self
+	}
+
+	/// Uploads the content as a single file through the legacy path.
+	///
+	/// The content path must be a single file under 10MB, or a directory containing one.
+	#[must_use]
+	pub fn allow_legacy_upload(mut self, allow: bool) -> Self {
+		unsafe {
+			assert!(sys::SteamAPI_ISteamUGC_SetAllowLegacyUpload(self.ugc, self.handle, allow));
+		}
+		self.changes.insert(UpdateChanges::LEGACY_UPLOAD);
+		self
+	}
+
+	/// Adds a key value tag. A key can have several values.
+	#[must_use]
+	pub fn add_key_value_tag(mut self, key: &str, value: &str) -> Self {
+		unsafe {
+			let key = CString::new(key).unwrap();
+			let value = CString::new(value).unwrap();
+			assert!(sys::SteamAPI_ISteamUGC_AddItemKeyValueTag(self.ugc, self.handle, key.as_ptr(), value.as_ptr()));
+		}
+		self.changes.insert(UpdateChanges::KEY_VALUE_TAGS);
+		self
+	}
+
+	/// Removes every existing key value tag with the given key.
+	#[must_use]
+	pub fn remove_key_value_tags(mut self, key: &str) -> Self {
+		unsafe {
+			let key = CString::new(key).unwrap();
+			assert!(sys::SteamAPI_ISteamUGC_RemoveItemKeyValueTags(self.ugc, self.handle, key.as_ptr()));
+		}
+		self.changes.insert(UpdateChanges::KEY_VALUE_TAGS);
+		self
+	}
+
+	/// Removes all of the item's existing key value tags.
+	#[must_use]
+	pub fn remove_all_key_value_tags(mut self) -> Self {
+		unsafe {
+			assert!(sys::SteamAPI_ISteamUGC_RemoveAllItemKeyValueTags(self.ugc, self.handle));
+		}
+		self.changes.insert(UpdateChanges::KEY_VALUE_TAGS);
+		self
+	}
+
+	/// Adds an additional preview file, which must be under 1MB.
+	#[must_use]
+	pub fn add_preview_file(mut self, path: &Path, preview_type: ItemPreviewType) -> Self {
+		unsafe {
+			let path = path.canonicalize().unwrap();
+			let preview_path = CString::new(&*path.to_string_lossy()).unwrap();
+			assert!(raw_enums::AddItemPreviewFile(self.ugc, self.handle, preview_path.as_ptr(), preview_type.to_raw()));
+		}
+		self.changes.insert(UpdateChanges::ADDITIONAL_PREVIEWS);
+		self
+	}
+
+	/// Adds an additional preview YouTube video.
+	#[must_use]
+	pub fn add_preview_video(mut self, video_id: &str) -> Self {
+		unsafe {
+			let video_id = CString::new(video_id).unwrap();
+			assert!(sys::SteamAPI_ISteamUGC_AddItemPreviewVideo(self.ugc, self.handle, video_id.as_ptr()));
+		}
+		self.changes.insert(UpdateChanges::ADDITIONAL_PREVIEWS);
+		self
+	}
+
+	/// Replaces the additional preview file at `index`, which must be under 1MB.
+	#[must_use]
+	pub fn update_preview_file(mut self, index: u32, path: &Path) -> Self {
+		unsafe {
+			let path = path.canonicalize().unwrap();
+			let preview_path = CString::new(&*path.to_string_lossy()).unwrap();
+			assert!(sys::SteamAPI_ISteamUGC_UpdateItemPreviewFile(self.ugc, self.handle, index, preview_path.as_ptr()));
+		}
+		self.changes.insert(UpdateChanges::ADDITIONAL_PREVIEWS);
+		self
+	}
+
+	/// Replaces the additional preview video at `index`.
+	#[must_use]
+	pub fn update_preview_video(mut self, index: u32, video_id: &str) -> Self {
+		unsafe {
+			let video_id = CString::new(video_id).unwrap();
+			assert!(sys::SteamAPI_ISteamUGC_UpdateItemPreviewVideo(self.ugc, self.handle, index, video_id.as_ptr()));
+		}
+		self.changes.insert(UpdateChanges::ADDITIONAL_PREVIEWS);
+		self
+	}
+
+	/// Removes the additional preview at `index`, in the order `QueryResults::additional_previews` returns them.
+	#[must_use]
+	pub fn remove_preview(mut self, index: u32) -> Self {
+		unsafe {
+			assert!(sys::SteamAPI_ISteamUGC_RemoveItemPreview(self.ugc, self.handle, index));
+		}
+		self.changes.insert(UpdateChanges::ADDITIONAL_PREVIEWS);
+		self
+	}
+
+	#[must_use]
+	pub fn add_content_descriptor(mut self, descriptor: ContentDescriptor) -> Self {
+		unsafe {
+			assert!(raw_enums::AddContentDescriptor(self.ugc, self.handle, descriptor.to_raw()));
+		}
+		self.changes.insert(UpdateChanges::CONTENT_DESCRIPTORS);
+		self
+	}
+
+	#[must_use]
+	pub fn remove_content_descriptor(mut self, descriptor: ContentDescriptor) -> Self {
+		unsafe {
+			assert!(raw_enums::RemoveContentDescriptor(self.ugc, self.handle, descriptor.to_raw()));
+		}
+		self.changes.insert(UpdateChanges::CONTENT_DESCRIPTORS);
 		self
 	}
 
@@ -672,6 +1195,7 @@ impl <Manager> UpdateHandle<Manager> {
 			ugc: self.ugc,
 			_inner: self.inner,
 			handle: self.handle,
+			changes: self.changes,
 		}
 	}
 }
@@ -682,12 +1206,16 @@ pub struct UpdateWatchHandle<Manager> {
 	_inner: Arc<Inner<Manager>>,
 
 	handle: sys::UGCUpdateHandle_t,
+	changes: UpdateChanges,
 }
 
 unsafe impl <Manager> Send for UpdateWatchHandle<Manager> {}
 unsafe impl <Manager> Sync for UpdateWatchHandle<Manager> {}
 
 impl <Manager> UpdateWatchHandle<Manager> {
+	/// Returns the current stage of the update, along with the bytes processed and total for it.
+	///
+	/// See `processing` for which of the submitted changes the stage covers.
 	pub fn progress(&self) -> (UpdateStatus, u64, u64) {
 		unsafe {
 			let mut progress = 0;
@@ -700,49 +1228,92 @@ impl <Manager> UpdateWatchHandle<Manager> {
 				sys::EItemUpdateStatus::k_EItemUpdateStatusUploadingContent => UpdateStatus::UploadingContent,
 				sys::EItemUpdateStatus::k_EItemUpdateStatusUploadingPreviewFile => UpdateStatus::UploadingPreviewFile,
 				sys::EItemUpdateStatus::k_EItemUpdateStatusCommittingChanges => UpdateStatus::CommittingChanges,
-				_ => unreachable!(),
+				_ => UpdateStatus::Invalid,
 			};
 			(status, progress, total)
 		}
 	}
+
+	/// Returns everything this update submitted.
+	pub fn changes(&self) -> UpdateChanges {
+		self.changes
+	}
+
+	/// Returns the submitted changes that the current stage of the update is processing.
+	pub fn processing(&self) -> UpdateChanges {
+		self.changes & self.progress().0.changes()
+	}
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UpdateStatus {
+	/// The update handle was invalid, or the update has finished.
 	Invalid,
+	/// Processing configuration data, such as the title, metadata and visibility.
 	PreparingConfig,
+	/// Reading and processing content files.
 	PreparingContent,
+	/// Uploading content changes to Steam.
 	UploadingContent,
+	/// Uploading the new preview file and any additional preview files.
 	UploadingPreviewFile,
+	/// Committing all changes.
 	CommittingChanges,
 }
 
-/// Query object from `query_user`, to allow for more filtering.
-pub struct UserListQuery<Manager> {
-	ugc: *mut sys::ISteamUGC,
-	inner: Arc<Inner<Manager>>,
-
-	// Note: this is always filled except in `fetch`, where it must be taken
-	// to prevent the handle from being dropped when this query is dropped.
-	handle: Option<sys::UGCQueryHandle_t>,
-}
-impl <Manager> Drop for UserListQuery<Manager> {
-	fn drop(&mut self) {
-		if let Some(handle) = self.handle.as_mut() {
-			unsafe {
-				sys::SteamAPI_ISteamUGC_ReleaseQueryUGCRequest(self.ugc, *handle);
-			}
+impl UpdateStatus {
+	/// Returns the kinds of changes that Steam processes during this stage.
+	pub fn changes(self) -> UpdateChanges {
+		match self {
+			UpdateStatus::Invalid => UpdateChanges::empty(),
+			UpdateStatus::PreparingConfig => {
+				UpdateChanges::TITLE
+					| UpdateChanges::DESCRIPTION
+					| UpdateChanges::TAGS
+					| UpdateChanges::LANGUAGE
+					| UpdateChanges::METADATA
+					| UpdateChanges::VISIBILITY
+					| UpdateChanges::KEY_VALUE_TAGS
+					| UpdateChanges::CONTENT_DESCRIPTORS
+			},
+			UpdateStatus::PreparingContent | UpdateStatus::UploadingContent => UpdateChanges::CONTENT | UpdateChanges::LEGACY_UPLOAD,
+			UpdateStatus::UploadingPreviewFile => UpdateChanges::PREVIEW | UpdateChanges::ADDITIONAL_PREVIEWS,
+			UpdateStatus::CommittingChanges => UpdateChanges::all(),
 		}
 	}
 }
-impl <Manager> UserListQuery<Manager> {
-	/// Excludes items with a specific tag.
-	///
-	/// Panics if `tag` could not be converted to a `CString`.
-	pub fn exclude_tag(self, tag: &str) -> Self {
-		let cstr = CString::new(tag).expect("String passed to exclude_tag could not be converted to a c string");
+
+bitflags! {
+	/// The parts of an item that an `UpdateHandle` changes.
+	pub struct UpdateChanges: u32 {
+		const TITLE = 1;
+		const DESCRIPTION = 2;
+		const PREVIEW = 4;
+		const CONTENT = 8;
+		const TAGS = 16;
+		const LANGUAGE = 32;
+		const METADATA = 64;
+		const VISIBILITY = 128;
+		const LEGACY_UPLOAD = 256;
+		const KEY_VALUE_TAGS = 512;
+		const ADDITIONAL_PREVIEWS = 1024;
+		const CONTENT_DESCRIPTORS = 2048;
+	}
+}
+
+/// Options shared by every kind of UGC query.
+pub trait QueryOptions: Sized {
+	#[doc(hidden)]
+	fn query_handle(&self) -> (*mut sys::ISteamUGC, sys::UGCQueryHandle_t);
+
+	/// Excludes items with a specific tag.
+	///
+	/// Panics if `tag` could not be converted to a `CString`.
+	fn exclude_tag(self, tag: &str) -> Self {
+		let (ugc, handle) = self.query_handle();
+		let cstr = CString::new(tag).expect("String passed to exclude_tag could not be converted to a c string");
 		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_AddExcludedTag(self.ugc, self.handle.unwrap(), cstr.as_ptr())
+			sys::SteamAPI_ISteamUGC_AddExcludedTag(ugc, handle, cstr.as_ptr())
 		};
 		debug_assert!(ok);
 		self
@@ -751,19 +1322,58 @@ impl <Manager> UserListQuery<Manager> {
 	/// Only include items with a specific tag.
 	///
 	/// Panics if `tag` could not be converted to a `CString`.
-	pub fn require_tag(self, tag: &str) -> Self {
+	fn require_tag(self, tag: &str) -> Self {
+		let (ugc, handle) = self.query_handle();
 		let cstr = CString::new(tag).expect("String passed to require_tag could not be converted to a c string");
 		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_AddRequiredTag(self.ugc, self.handle.unwrap(), cstr.as_ptr())
+			sys::SteamAPI_ISteamUGC_AddRequiredTag(ugc, handle, cstr.as_ptr())
 		};
 		debug_assert!(ok);
 		self
 	}
 
-	/// Sets how to match tags added by `require_tag`. If `true`, then any tag may match. If `false`, all required tags must match.
-	pub fn any_required(self, any: bool) -> Self {
+	/// Only include items with at least one of the tags in the group.
+	///
+	/// Panics if any of the tags could not be converted to a `CString`.
+	fn require_tag_group<S: AsRef<str>>(self, tags: &[S]) -> Self {
+		let (ugc, handle) = self.query_handle();
+		let mut tags = SteamParamStringArray::new(tags);
 		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetMatchAnyTag(self.ugc, self.handle.unwrap(), any)
+			sys::SteamAPI_ISteamUGC_AddRequiredTagGroup(ugc, handle, &tags.as_raw())
+		};
+		debug_assert!(ok);
+		self
+	}
+
+	/// Only include items with a key value tag matching `key` and `value`.
+	///
+	/// Panics if `key` or `value` could not be converted to a `CString`.
+	fn require_key_value_tag(self, key: &str, value: &str) -> Self {
+		let key = CString::new(key).expect("String passed to require_key_value_tag could not be converted to a c string");
+		let value = CString::new(value).expect("String passed to require_key_value_tag could not be converted to a c string");
+		let (ugc, handle) = self.query_handle();
+		let ok = unsafe {
+			sys::SteamAPI_ISteamUGC_AddRequiredKeyValueTag(ugc, handle, key.as_ptr(), value.as_ptr())
+		};
+		debug_assert!(ok);
+		self
+	}
+
+	/// Only return the IDs of the items, rather than all of their details.
+	fn return_only_ids(self, only_ids: bool) -> Self {
+		let (ugc, handle) = self.query_handle();
+		let ok = unsafe {
+			sys::SteamAPI_ISteamUGC_SetReturnOnlyIDs(ugc, handle, only_ids)
+		};
+		debug_assert!(ok);
+		self
+	}
+
+	/// Only return the total number of matching items.
+	fn return_total_only(self, total_only: bool) -> Self {
+		let (ugc, handle) = self.query_handle();
+		let ok = unsafe {
+			sys::SteamAPI_ISteamUGC_SetReturnTotalOnly(ugc, handle, total_only)
 		};
 		debug_assert!(ok);
 		self
@@ -772,10 +1382,11 @@ impl <Manager> UserListQuery<Manager> {
 	/// Sets the language to return the title and description in for the items on a pending UGC Query.
 	///
 	/// Defaults to "english"
-	pub fn language(self, language: &str) -> Self {
+	fn language(self, language: &str) -> Self {
+		let (ugc, handle) = self.query_handle();
 		let cstr = CString::new(language).expect("String passed to language could not be converted to a c string");
 		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetLanguage(self.ugc, self.handle.unwrap(), cstr.as_ptr())
+			sys::SteamAPI_ISteamUGC_SetLanguage(ugc, handle, cstr.as_ptr())
 		};
 		debug_assert!(ok);
 		self
@@ -784,54 +1395,125 @@ impl <Manager> UserListQuery<Manager> {
 	/// Sets whether results will be returned from the cache for the specific period of time on a pending UGC Query.
 	///
 	/// Age is in seconds.
-	pub fn allow_cached_response(self, max_age_s: u32) -> Self {
+	fn allow_cached_response(self, max_age_s: u32) -> Self {
+		let (ugc, handle) = self.query_handle();
 		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetAllowCachedResponse(self.ugc, self.handle.unwrap(), max_age_s)
+			sys::SteamAPI_ISteamUGC_SetAllowCachedResponse(ugc, handle, max_age_s)
 		};
 		debug_assert!(ok);
 		self
 	}
 
 	/// Include the full description in results
-	pub fn include_long_desc(self, include: bool) -> Self {
+	fn include_long_desc(self, include: bool) -> Self {
+		let (ugc, handle) = self.query_handle();
 		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetReturnLongDescription(self.ugc, self.handle.unwrap(), include)
+			sys::SteamAPI_ISteamUGC_SetReturnLongDescription(ugc, handle, include)
 		};
 		debug_assert!(ok);
 		self
 	}
 
 	/// Include children in results
-	pub fn include_children(self, include: bool) -> Self {
+	fn include_children(self, include: bool) -> Self {
+		let (ugc, handle) = self.query_handle();
 		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetReturnChildren(self.ugc, self.handle.unwrap(), include)
+			sys::SteamAPI_ISteamUGC_SetReturnChildren(ugc, handle, include)
 		};
 		debug_assert!(ok);
 		self
 	}
 
 	/// Include metadata in results
-	pub fn include_metadata(self, include: bool) -> Self {
+	fn include_metadata(self, include: bool) -> Self {
+		let (ugc, handle) = self.query_handle();
 		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetReturnMetadata(self.ugc, self.handle.unwrap(), include)
+			sys::SteamAPI_ISteamUGC_SetReturnMetadata(ugc, handle, include)
 		};
 		debug_assert!(ok);
 		self
 	}
 
 	/// Include additional previews in results
-	pub fn include_additional_previews(self, include: bool) -> Self {
+	fn include_additional_previews(self, include: bool) -> Self {
+		let (ugc, handle) = self.query_handle();
 		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetReturnAdditionalPreviews(self.ugc, self.handle.unwrap(), include)
+			sys::SteamAPI_ISteamUGC_SetReturnAdditionalPreviews(ugc, handle, include)
 		};
 		debug_assert!(ok);
 		self
 	}
 
 	/// Include key value tags in results
-	pub fn include_key_value_tags(self, include: bool) -> Self {
+	fn include_key_value_tags(self, include: bool) -> Self {
+		let (ugc, handle) = self.query_handle();
+		let ok = unsafe {
+			sys::SteamAPI_ISteamUGC_SetReturnKeyValueTags(ugc, handle, include)
+		};
+		debug_assert!(ok);
+		self
+	}
+
+	/// Include playtime statistics for the given number of days in results
+	///
+	/// 0 returns the statistics for all time.
+	fn include_playtime_stats(self, days: u32) -> Self {
+		let (ugc, handle) = self.query_handle();
+		let ok = unsafe {
+			sys::SteamAPI_ISteamUGC_SetReturnPlaytimeStats(ugc, handle, days)
+		};
+		debug_assert!(ok);
+		self
+	}
+}
+
+/// Implements [`QueryOptions`] for a query type.
+macro_rules! query_options {
+	($query:ident) => {
+		impl <Manager> QueryOptions for $query<Manager> {
+			fn query_handle(&self) -> (*mut sys::ISteamUGC, sys::UGCQueryHandle_t) {
+				(self.ugc, self.handle.unwrap())
+			}
+		}
+	};
+}
+
+/// Query object from `query_all`, to allow for more filtering.
+pub struct AllQuery<Manager> {
+	ugc: *mut sys::ISteamUGC,
+	inner: Arc<Inner<Manager>>,
+
+	// Note: this is always filled except in `fetch`, where it must be taken
+	// to prevent the handle from being dropped when this query is dropped.
+	handle: Option<sys::UGCQueryHandle_t>,
+}
+impl <Manager> Drop for AllQuery<Manager> {
+	fn drop(&mut self) {
+		if let Some(handle) = self.handle.as_mut() {
+			unsafe {
+				sys::SteamAPI_ISteamUGC_ReleaseQueryUGCRequest(self.ugc, *handle);
+			}
+		}
+	}
+}
+query_options!(AllQuery);
+impl <Manager> AllQuery<Manager> {
+	/// Sets how to match tags added by `require_tag`. If `true`, then any tag may match. If `false`, all required tags must match.
+	pub fn any_required(self, any: bool) -> Self {
+		let ok = unsafe {
+			sys::SteamAPI_ISteamUGC_SetMatchAnyTag(self.ugc, self.handle.unwrap(), any)
+		};
+		debug_assert!(ok);
+		self
+	}
+
+	/// Only include items whose title or description contains `text`.
+	///
+	/// Panics if `text` could not be converted to a `CString`.
+	pub fn search_text(self, text: &str) -> Self {
+		let cstr = CString::new(text).expect("String passed to search_text could not be converted to a c string");
 		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetReturnKeyValueTags(self.ugc, self.handle.unwrap(), include)
+			sys::SteamAPI_ISteamUGC_SetSearchText(self.ugc, self.handle.unwrap(), cstr.as_ptr())
 		};
 		debug_assert!(ok);
 		self
@@ -879,30 +1561,21 @@ impl <Manager> UserListQuery<Manager> {
 	pub fn fetch_total<F>(self, cb: F)
 		where F: Fn(Result<u32, SteamError>) + 'static + Send
 	{
-		unsafe {
-			let ok = sys::SteamAPI_ISteamUGC_SetReturnTotalOnly(self.ugc, self.handle.unwrap(), true);
-			debug_assert!(ok);
-		}
-
-		self.fetch(move |res| cb(res.map(|qr| qr.total_results())))
+		self.return_total_only(true)
+			.fetch(move |res| cb(res.map(|qr| qr.total_results())))
 	}
 
 	/// Runs the query, only fetching the IDs.
 	pub fn fetch_ids<F>(self, cb: F)
 		where F: Fn(Result<Vec<PublishedFileId>, SteamError>) + 'static + Send
 	{
-		unsafe {
-			let ok = sys::SteamAPI_ISteamUGC_SetReturnOnlyIDs(self.ugc, self.handle.unwrap(), true);
-			debug_assert!(ok);
-		}
-
-		self.fetch(move |res|
-			cb(res.map(|qr| qr.iter().filter_map(|v| v.map(|v| PublishedFileId(v.m_nPublishedFileId))).collect::<Vec<_>>())))
+		self.return_only_ids(true).fetch(move |res|
+			cb(res.map(|qr| qr.iter().filter_map(|v| v.map(|v| v.published_file_id)).collect::<Vec<_>>())))
 	}
 }
 
-/// Query object from `query_items`, to allow for more filtering.
-pub struct ItemListDetailsQuery<Manager> {
+/// Query object from `query_user`, to allow for more filtering.
+pub struct UserListQuery<Manager> {
 	ugc: *mut sys::ISteamUGC,
 	inner: Arc<Inner<Manager>>,
 
@@ -910,7 +1583,7 @@ pub struct ItemListDetailsQuery<Manager> {
 	// to prevent the handle from being dropped when this query is dropped.
 	handle: Option<sys::UGCQueryHandle_t>,
 }
-impl <Manager> Drop for ItemListDetailsQuery<Manager> {
+impl <Manager> Drop for UserListQuery<Manager> {
 	fn drop(&mut self) {
 		if let Some(handle) = self.handle.as_mut() {
 			unsafe {
@@ -919,79 +1592,108 @@ impl <Manager> Drop for ItemListDetailsQuery<Manager> {
 		}
 	}
 }
-impl <Manager> ItemListDetailsQuery<Manager> {
-	/// Sets how to match tags added by `require_tag`. If `true`, then any tag may match. If `false`, all required tags must match.
-	pub fn any_required(self, any: bool) -> Self {
-		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetMatchAnyTag(self.ugc, self.handle.unwrap(), any)
-		};
-		debug_assert!(ok);
-		self
-	}
-
-	/// Sets the language to return the title and description in for the items on a pending UGC Query.
+query_options!(UserListQuery);
+impl <Manager> UserListQuery<Manager> {
+	/// Only include items whose cloud file name matches `file_name`.
 	///
-	/// Defaults to "english"
-	pub fn language(self, language: &str) -> Self {
-		let cstr = CString::new(language).expect("String passed to language could not be converted to a c string");
+	/// Panics if `file_name` could not be converted to a `CString`.
+	pub fn cloud_file_name_filter(self, file_name: &str) -> Self {
+		let cstr = CString::new(file_name).expect("String passed to cloud_file_name_filter could not be converted to a c string");
 		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetLanguage(self.ugc, self.handle.unwrap(), cstr.as_ptr())
+			sys::SteamAPI_ISteamUGC_SetCloudFileNameFilter(self.ugc, self.handle.unwrap(), cstr.as_ptr())
 		};
 		debug_assert!(ok);
 		self
 	}
 
-	/// Sets whether results will be returned from the cache for the specific period of time on a pending UGC Query.
-	///
-	/// Age is in seconds.
-	pub fn allow_cached_response(self, max_age_s: u32) -> Self {
+	/// Sets how to match tags added by `require_tag`. If `true`, then any tag may match. If `false`, all required tags must match.
+	pub fn any_required(self, any: bool) -> Self {
 		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetAllowCachedResponse(self.ugc, self.handle.unwrap(), max_age_s)
+			sys::SteamAPI_ISteamUGC_SetMatchAnyTag(self.ugc, self.handle.unwrap(), any)
 		};
 		debug_assert!(ok);
 		self
 	}
 
-	/// Include the full description in results
-	pub fn include_long_desc(self, include: bool) -> Self {
-		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetReturnLongDescription(self.ugc, self.handle.unwrap(), include)
-		};
-		debug_assert!(ok);
-		self
-	}
+	/// Runs the query
+	pub fn fetch<F>(mut self, cb: F)
+		where F: for<'a> FnOnce(Result<QueryResults<'a>,SteamError>) + 'static + Send
+	{
+		let ugc = self.ugc;
+		let inner = Arc::clone(&self.inner);
+		let handle = self.handle.take().unwrap();
+		mem::drop(self);
 
-	/// Include children in results
-	pub fn include_children(self, include: bool) -> Self {
-		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetReturnChildren(self.ugc, self.handle.unwrap(), include)
-		};
-		debug_assert!(ok);
-		self
+		unsafe {
+			let api_call = sys::SteamAPI_ISteamUGC_SendQueryUGCRequest(ugc, handle);
+			register_call_result::<sys::SteamUGCQueryCompleted_t, _, _>(
+				&inner, api_call, CALLBACK_BASE_ID + 1,
+				move |v, io_error| {
+					let ugc = sys_ugc!();
+					if io_error {
+						sys::SteamAPI_ISteamUGC_ReleaseQueryUGCRequest(ugc, handle);
+						cb(Err(SteamError::IOFailure));
+						return;
+					} else if v.m_eResult != sys::EResult::k_EResultOK {
+						sys::SteamAPI_ISteamUGC_ReleaseQueryUGCRequest(ugc, handle);
+						cb(Err(v.m_eResult.into()));
+						return;
+					}
+
+					let result = QueryResults {
+						ugc,
+						handle,
+						num_results_returned: v.m_unNumResultsReturned,
+						num_results_total: v.m_unTotalMatchingResults,
+						was_cached: v.m_bCachedData,
+						_phantom: Default::default(),
+					};
+					cb(Ok(result));
+			});
+		}
 	}
 
-	/// Include metadata in results
-	pub fn include_metadata(self, include: bool) -> Self {
-		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetReturnMetadata(self.ugc, self.handle.unwrap(), include)
-		};
-		debug_assert!(ok);
-		self
+	/// Runs the query, only fetching the total number of results.
+	pub fn fetch_total<F>(self, cb: F)
+		where F: Fn(Result<u32, SteamError>) + 'static + Send
+	{
+		self.return_total_only(true)
+			.fetch(move |res| cb(res.map(|qr| qr.total_results())))
 	}
 
-	/// Include additional previews in results
-	pub fn include_additional_previews(self, include: bool) -> Self {
-		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetReturnAdditionalPreviews(self.ugc, self.handle.unwrap(), include)
-		};
-		debug_assert!(ok);
-		self
+	/// Runs the query, only fetching the IDs.
+	pub fn fetch_ids<F>(self, cb: F)
+		where F: Fn(Result<Vec<PublishedFileId>, SteamError>) + 'static + Send
+	{
+		self.return_only_ids(true).fetch(move |res|
+			cb(res.map(|qr| qr.iter().filter_map(|v| v.map(|v| v.published_file_id)).collect::<Vec<_>>())))
 	}
+}
 
-	/// Include key value tags in results
-	pub fn include_key_value_tags(self, include: bool) -> Self {
+/// Query object from `query_items`, to allow for more filtering.
+pub struct ItemListDetailsQuery<Manager> {
+	ugc: *mut sys::ISteamUGC,
+	inner: Arc<Inner<Manager>>,
+
+	// Note: this is always filled except in `fetch`, where it must be taken
+	// to prevent the handle from being dropped when this query is dropped.
+	handle: Option<sys::UGCQueryHandle_t>,
+}
+impl <Manager> Drop for ItemListDetailsQuery<Manager> {
+	fn drop(&mut self) {
+		if let Some(handle) = self.handle.as_mut() {
+			unsafe {
+				sys::SteamAPI_ISteamUGC_ReleaseQueryUGCRequest(self.ugc, *handle);
+			}
+		}
+	}
+}
+query_options!(ItemListDetailsQuery);
+impl <Manager> ItemListDetailsQuery<Manager> {
+	/// Sets how to match tags added by `require_tag`. If `true`, then any tag may match. If `false`, all required tags must match.
+	pub fn any_required(self, any: bool) -> Self {
 		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetReturnKeyValueTags(self.ugc, self.handle.unwrap(), include)
+			sys::SteamAPI_ISteamUGC_SetMatchAnyTag(self.ugc, self.handle.unwrap(), any)
 		};
 		debug_assert!(ok);
 		self
@@ -1039,12 +1741,8 @@ impl <Manager> ItemListDetailsQuery<Manager> {
 	pub fn fetch_total<F>(self, cb: F)
 		where F: Fn(Result<u32, SteamError>) + 'static + Send
 	{
-		unsafe {
-			let ok = sys::SteamAPI_ISteamUGC_SetReturnTotalOnly(self.ugc, self.handle.unwrap(), true);
-			debug_assert!(ok);
-		}
-
-		self.fetch(move |res| cb(res.map(|qr| qr.total_results())))
+		self.return_total_only(true)
+			.fetch(move |res| cb(res.map(|qr| qr.total_results())))
 	}
 }
 
@@ -1066,66 +1764,8 @@ impl <Manager> Drop for ItemDetailsQuery<Manager> {
 		}
 	}
 }
+query_options!(ItemDetailsQuery);
 impl <Manager> ItemDetailsQuery<Manager> {
-	/// Sets the language to return the title and description in for the items on a pending UGC Query.
-	///
-	/// Defaults to "english"
-	pub fn language(self, language: &str) -> Self {
-		let cstr = CString::new(language).expect("String passed to language could not be converted to a c string");
-		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetLanguage(self.ugc, self.handle.unwrap(), cstr.as_ptr())
-		};
-		debug_assert!(ok);
-		self
-	}
-
-	/// Sets whether results will be returned from the cache for the specific period of time on a pending UGC Query.
-	///
-	/// Age is in seconds.
-	pub fn allow_cached_response(self, max_age_s: u32) -> Self {
-		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetAllowCachedResponse(self.ugc, self.handle.unwrap(), max_age_s)
-		};
-		debug_assert!(ok);
-		self
-	}
-
-	/// Include the full description in results
-	pub fn include_long_desc(self, include: bool) -> Self {
-		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetReturnLongDescription(self.ugc, self.handle.unwrap(), include)
-		};
-		debug_assert!(ok);
-		self
-	}
-
-	/// Include children in results
-	pub fn include_children(self, include: bool) -> Self {
-		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetReturnChildren(self.ugc, self.handle.unwrap(), include)
-		};
-		debug_assert!(ok);
-		self
-	}
-
-	/// Include metadata in results
-	pub fn include_metadata(self, include: bool) -> Self {
-		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetReturnMetadata(self.ugc, self.handle.unwrap(), include)
-		};
-		debug_assert!(ok);
-		self
-	}
-
-	/// Include additional previews in results
-	pub fn include_additional_previews(self, include: bool) -> Self {
-		let ok = unsafe {
-			sys::SteamAPI_ISteamUGC_SetReturnAdditionalPreviews(self.ugc, self.handle.unwrap(), include)
-		};
-		debug_assert!(ok);
-		self
-	}
-
 	/// Runs the query
 	pub fn fetch<F>(mut self, cb: F)
 		where F: for<'a> FnOnce(Result<QueryResults<'a>,SteamError>) + 'static + Send
@@ -1236,7 +1876,7 @@ impl<'a> QueryResults<'a> {
 	/// Gets a result.
 	///
 	/// Returns None if index was out of bounds.
-	pub fn get(&self, index: u32) -> Option<sys::SteamUGCDetails_t> {
+	pub fn get(&self, index: u32) -> Option<QueryResult> {
 		if index >= self.num_results_returned {
 			return None;
 		}
@@ -1248,48 +1888,12 @@ impl<'a> QueryResults<'a> {
 
 			if raw_details.m_eResult != sys::EResult::k_EResultOK { return None }
 
-			/*
-			let tags = CStr::from_ptr(raw_details.m_rgchTags.as_ptr())
-				.to_string_lossy()
-				.split(',')
-				.map(|s| String::from(s))
-				.collect::<Vec<_>>();
-
-			Some(QueryResult {
-				published_file_id: PublishedFileId(raw_details.m_nPublishedFileId),
-				creator_app_id: if raw_details.m_nCreatorAppID != 0 { Some(AppId(raw_details.m_nCreatorAppID)) } else { None },
-				consumer_app_id: if raw_details.m_nConsumerAppID != 0 { Some(AppId(raw_details.m_nConsumerAppID)) } else { None },
-				title: CStr::from_ptr(raw_details.m_rgchTitle.as_ptr())
-					.to_string_lossy()
-					.into_owned(),
-				description: CStr::from_ptr(raw_details.m_rgchDescription.as_ptr())
-					.to_string_lossy()
-					.into_owned(),
-				owner: SteamId(raw_details.m_ulSteamIDOwner),
-				time_created: raw_details.m_rtimeCreated,
-				time_updated: raw_details.m_rtimeUpdated,
-				banned: raw_details.m_bBanned,
-				accepted_for_use: raw_details.m_bAcceptedForUse,
-				url: CStr::from_ptr(raw_details.m_rgchURL.as_ptr())
-					.to_string_lossy()
-					.into_owned(),
-				num_upvotes: raw_details.m_unVotesUp,
-				num_downvotes: raw_details.m_unVotesDown,
-				score: raw_details.m_flScore,
-				num_children: raw_details.m_unNumChildren,
-				tags,
-				tags_truncated: raw_details.m_bTagsTruncated,
-				file_type: raw_details.m_eFileType.into(),
-				file_size: raw_details.m_nFileSize.max(0) as u32,
-			})
-			*/
-
-			Some(raw_details)
+			Some(QueryResult::from_raw(&raw_details))
 		}
 	}
 
 	/// Returns an iterator that runs over all the fetched results
-	pub fn iter<'b>(&'b self) -> impl Iterator<Item=Option<sys::SteamUGCDetails_t>> + 'b {
+	pub fn iter<'b>(&'b self) -> impl Iterator<Item=Option<QueryResult>> + 'b {
 		(0..self.returned_results())
 			.map(move |i| self.get(i))
 	}
@@ -1300,7 +1904,7 @@ impl<'a> QueryResults<'a> {
 	///
 	/// Returns None if the index was out of bounds.
 	pub fn get_children(&self, index: u32) -> Option<Vec<PublishedFileId>> {
-		let num_children = self.get(index)?.m_unNumChildren;
+		let num_children = self.get(index)?.num_children;
 		let mut children: Vec<sys::PublishedFileId_t> = vec![0; num_children as usize];
 
 		let ok = unsafe {
@@ -1364,6 +1968,121 @@ impl<'a> QueryResults<'a> {
 			None
 		}
 	}
+
+	/// Returns the number of tags associated with the item at the specified index.
+	pub fn num_tags(&self, index: u32) -> u32 {
+		unsafe { sys::SteamAPI_ISteamUGC_GetQueryUGCNumTags(self.ugc, self.handle, index) }
+	}
+
+	/// Gets a tag associated with the item at the specified index.
+	pub fn tag(&self, index: u32, tag_index: u32) -> Option<String> {
+		let mut tag = [0 as c_char; 256];
+
+		let ok = unsafe {
+			sys::SteamAPI_ISteamUGC_GetQueryUGCTag(self.ugc, self.handle, index, tag_index, tag.as_mut_ptr(), tag.len() as _)
+		};
+
+		if ok {
+			Some(unsafe { CStr::from_ptr(tag.as_ptr()).to_string_lossy().into_owned() })
+		} else {
+			None
+		}
+	}
+
+	/// Gets the display name of a tag associated with the item at the specified index.
+	///
+	/// This is the name shown on the Workshop, which may differ from the tag itself.
+	pub fn tag_display_name(&self, index: u32, tag_index: u32) -> Option<String> {
+		let mut name = [0 as c_char; 256];
+
+		let ok = unsafe {
+			sys::SteamAPI_ISteamUGC_GetQueryUGCTagDisplayName(self.ugc, self.handle, index, tag_index, name.as_mut_ptr(), name.len() as _)
+		};
+
+		if ok {
+			Some(unsafe { CStr::from_ptr(name.as_ptr()).to_string_lossy().into_owned() })
+		} else {
+			None
+		}
+	}
+
+	/// Returns the tags associated with the item at the specified index, paired with their display names.
+	pub fn tags_with_display_names(&self, index: u32) -> Vec<(String, String)> {
+		(0..self.num_tags(index))
+			.filter_map(|i| Some((self.tag(index, i)?, self.tag_display_name(index, i)?)))
+			.collect()
+	}
+
+	/// Returns the number of additional previews of the item at the specified index.
+	///
+	/// You must call `include_additional_previews(true)` before fetching the query for this to work.
+	pub fn num_additional_previews(&self, index: u32) -> u32 {
+		unsafe { sys::SteamAPI_ISteamUGC_GetQueryUGCNumAdditionalPreviews(self.ugc, self.handle, index) }
+	}
+
+	/// Gets an additional preview of the item at the specified index.
+	///
+	/// You must call `include_additional_previews(true)` before fetching the query for this to work.
+	pub fn additional_preview(&self, index: u32, preview_index: u32) -> Option<AdditionalPreview> {
+		let mut url = [0 as c_char; 4096];
+		let mut file_name = [0 as c_char; 260];
+		// Read as an integer, Steam may return types the bindings don't know about
+		let mut preview_type = 0u32;
+
+		let ok = unsafe {
+			sys::SteamAPI_ISteamUGC_GetQueryUGCAdditionalPreview(
+				self.ugc,
+				self.handle,
+				index,
+				preview_index,
+				url.as_mut_ptr(),
+				url.len() as _,
+				file_name.as_mut_ptr(),
+				file_name.len() as _,
+				&mut preview_type as *mut u32 as *mut sys::EItemPreviewType,
+			)
+		};
+
+		if ok {
+			let original_file_name = unsafe { CStr::from_ptr(file_name.as_ptr()).to_string_lossy().into_owned() };
+			Some(AdditionalPreview {
+				url_or_video_id: unsafe { CStr::from_ptr(url.as_ptr()).to_string_lossy().into_owned() },
+				original_file_name: Some(original_file_name).filter(|name| !name.is_empty()),
+				preview_type: ItemPreviewType::from_raw(preview_type),
+			})
+		} else {
+			None
+		}
+	}
+
+	/// Returns all of the additional previews of the item at the specified index.
+	///
+	/// You must call `include_additional_previews(true)` before fetching the query for this to work.
+	pub fn additional_previews(&self, index: u32) -> Vec<AdditionalPreview> {
+		(0..self.num_additional_previews(index))
+			.filter_map(|i| self.additional_preview(index, i))
+			.collect()
+	}
+
+	/// Returns the content descriptors the item at the specified index has been flagged with.
+	pub fn content_descriptors(&self, index: u32) -> Vec<ContentDescriptor> {
+		let mut descriptors = [0u32; 32];
+
+		let count = unsafe {
+			sys::SteamAPI_ISteamUGC_GetQueryUGCContentDescriptors(
+				self.ugc,
+				self.handle,
+				index,
+				descriptors.as_mut_ptr() as *mut sys::EUGCContentDescriptorID,
+				descriptors.len() as _,
+			)
+		};
+
+		descriptors[..(count as usize).min(descriptors.len())]
+			.iter()
+			.map(|descriptor| ContentDescriptor::from_raw(*descriptor))
+			.collect()
+	}
 }
 
 /// Query result
@@ -1379,12 +2098,21 @@ pub struct QueryResult {
 	pub time_created: u32,
 	/// Time updated in unix epoch seconds format
 	pub time_updated: u32,
+	/// Time when the user added the item to their list, for user list queries
+	pub time_added_to_user_list: u32,
+	pub visibility: PublishedFileVisibility,
 	pub banned: bool,
 	pub accepted_for_use: bool,
 	pub tags: Vec<String>,
+	/// Whether `tags` was cut short because the item has too many
 	pub tags_truncated: bool,
 	pub file_type: FileType,
+	/// The primary file, `None` for items without one
+	pub file: Option<UGCHandle>,
+	pub file_name: String,
 	pub file_size: u32,
+	pub preview_file: Option<UGCHandle>,
+	pub preview_file_size: u32,
 
 	pub url: String,
 	pub num_upvotes: u32,
@@ -1393,7 +2121,89 @@ pub struct QueryResult {
 	pub score: f32,
 	pub num_children: u32,
 
-	// TODO: Add missing fields as needed
+	/// `title`, `description`, the comma-separated tags and `url` exactly as Steam returned them.
+	/// The `String` fields above replace any invalid UTF-8.
+	pub raw_title: Vec<u8>,
+	pub raw_description: Vec<u8>,
+	pub raw_tags: Vec<u8>,
+	pub raw_url: Vec<u8>,
+}
+impl QueryResult {
+	/// Copies everything out of the raw struct.
+	///
+	/// The SDK packs `SteamUGCDetails_t` to 4 bytes on 64-bit Linux, so its 64-bit fields may be
+	/// unaligned there. Every field is read through a raw pointer so that this works with all bindings.
+	fn from_raw(raw: &sys::SteamUGCDetails_t) -> QueryResult {
+		macro_rules! read {
+			($field:ident) => {
+				unsafe { std::ptr::read_unaligned(std::ptr::addr_of!(raw.$field)) }
+			};
+		}
+
+		fn bytes(chars: &[c_char]) -> Vec<u8> {
+			let bytes = unsafe { std::slice::from_raw_parts(chars.as_ptr() as *const u8, chars.len()) };
+			bytes.split(|b| *b == 0).next().unwrap_or_default().to_vec()
+		}
+
+		fn string(bytes: &[u8]) -> String {
+			String::from_utf8_lossy(bytes).into_owned()
+		}
+
+		fn handle(handle: sys::UGCHandle_t) -> Option<UGCHandle> {
+			if handle != 0 && handle != sys::k_UGCHandleInvalid {
+				Some(UGCHandle(handle))
+			} else {
+				None
+			}
+		}
+
+		let app_id = |id: sys::AppId_t| if id != 0 { Some(AppId(id)) } else { None };
+
+		let raw_title = bytes(&read!(m_rgchTitle));
+		let raw_description = bytes(&read!(m_rgchDescription));
+		let raw_tags = bytes(&read!(m_rgchTags));
+		let raw_url = bytes(&read!(m_rgchURL));
+
+		// Read enums as plain integers: values newer than these bindings aren't valid for the Rust enums
+		let visibility = unsafe { std::ptr::read_unaligned(std::ptr::addr_of!(raw.m_eVisibility) as *const u32) };
+		let file_type = unsafe { std::ptr::read_unaligned(std::ptr::addr_of!(raw.m_eFileType) as *const u32) };
+
+		QueryResult {
+			published_file_id: PublishedFileId(read!(m_nPublishedFileId)),
+			creator_app_id: app_id(read!(m_nCreatorAppID)),
+			consumer_app_id: app_id(read!(m_nConsumerAppID)),
+			title: string(&raw_title),
+			description: string(&raw_description),
+			owner: SteamId(read!(m_ulSteamIDOwner)),
+			time_created: read!(m_rtimeCreated),
+			time_updated: read!(m_rtimeUpdated),
+			time_added_to_user_list: read!(m_rtimeAddedToUserList),
+			visibility: PublishedFileVisibility::from_raw(visibility),
+			banned: read!(m_bBanned),
+			accepted_for_use: read!(m_bAcceptedForUse),
+			tags: string(&raw_tags)
+				.split(',')
+				.filter(|tag| !tag.is_empty())
+				.map(String::from)
+				.collect(),
+			tags_truncated: read!(m_bTagsTruncated),
+			file_type: FileType::from_raw(file_type),
+			file: handle(read!(m_hFile)),
+			file_name: string(&bytes(&read!(m_pchFileName))),
+			file_size: read!(m_nFileSize).max(0) as u32,
+			preview_file: handle(read!(m_hPreviewFile)),
+			preview_file_size: read!(m_nPreviewFileSize).max(0) as u32,
+			url: string(&raw_url),
+			num_upvotes: read!(m_unVotesUp),
+			num_downvotes: read!(m_unVotesDown),
+			score: read!(m_flScore),
+			num_children: read!(m_unNumChildren),
+			raw_title,
+			raw_description,
+			raw_tags,
+			raw_url,
+		}
+	}
 }
 
 #[derive(Debug,Clone,Copy)]
@@ -1404,3 +2214,196 @@ impl fmt::Display for CreateQueryError {
 	}
 }
 impl error::Error for CreateQueryError {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Offsets of the `SteamUGCDetails_t` fields in the SDK headers. 64-bit fields are 4-aligned
+	/// on Linux (`#pragma pack(4)` on 64-bit, the i386 ABI on 32-bit) and 8-aligned on Windows.
+	const LINUX_LAYOUT: (usize, usize, [usize; 26]) = (9764, 4, [
+		0, 8, 12, 16, 20, 24, 153, 8156, 8164, 8168, 8172, 8176, 8180, 8181, 8182, 8183,
+		9208, 9216, 9224, 9484, 9488, 9492, 9748, 9752, 9756, 9760,
+	]);
+	const WINDOWS_LAYOUT: (usize, usize, [usize; 26]) = (9776, 8, [
+		0, 8, 12, 16, 20, 24, 153, 8160, 8168, 8172, 8176, 8180, 8184, 8185, 8186, 8187,
+		9216, 9224, 9232, 9492, 9496, 9500, 9756, 9760, 9764, 9768,
+	]);
+	#[cfg(target_os = "linux")]
+	const LAYOUT: (usize, usize, [usize; 26]) = LINUX_LAYOUT;
+	#[cfg(target_os = "windows")]
+	const LAYOUT: (usize, usize, [usize; 26]) = WINDOWS_LAYOUT;
+
+	const FIELDS: [&str; 26] = [
+		"m_nPublishedFileId", "m_eResult", "m_eFileType", "m_nCreatorAppID", "m_nConsumerAppID",
+		"m_rgchTitle", "m_rgchDescription", "m_ulSteamIDOwner", "m_rtimeCreated", "m_rtimeUpdated",
+		"m_rtimeAddedToUserList", "m_eVisibility", "m_bBanned", "m_bAcceptedForUse", "m_bTagsTruncated",
+		"m_rgchTags", "m_hFile", "m_hPreviewFile", "m_pchFileName", "m_nFileSize", "m_nPreviewFileSize",
+		"m_rgchURL", "m_unVotesUp", "m_unVotesDown", "m_flScore", "m_unNumChildren",
+	];
+
+	/// Reads the field names and `usize` literals (size, alignment, then each field's offset)
+	/// out of the layout test bindgen generated for `SteamUGCDetails_t`.
+	fn bindgen_layout(bindings: &str) -> (Vec<&str>, Vec<usize>) {
+		let body = bindings
+			.lines()
+			.skip_while(|line| !line.contains("fn bindgen_test_layout_SteamUGCDetails_t()"))
+			.take_while(|line| line.trim_end() != "}")
+			.collect::<Vec<_>>();
+		assert!(!body.is_empty(), "no layout test for SteamUGCDetails_t");
+
+		let fields = body
+			.iter()
+			.filter_map(|line| line.split(")).").nth(1))
+			.filter_map(|field| field.split(' ').next())
+			.collect();
+
+		let literals = body
+			.iter()
+			.flat_map(|line| line.split("usize"))
+			.filter_map(|chunk| chunk.rsplit(|c: char| !c.is_ascii_digit()).next())
+			.filter_map(|literal| literal.parse().ok())
+			.collect();
+
+		(fields, literals)
+	}
+
+	#[test]
+	fn ugc_details_bindings_layout() {
+		for (name, bindings, (size, align, offsets)) in [
+			("linux32", include_str!("../steamworks-sys/src/bindings_linux32.rs"), LINUX_LAYOUT),
+			("linux64", include_str!("../steamworks-sys/src/bindings_linux64.rs"), LINUX_LAYOUT),
+			("win32", include_str!("../steamworks-sys/src/bindings_win32.rs"), WINDOWS_LAYOUT),
+			("win64", include_str!("../steamworks-sys/src/bindings_win64.rs"), WINDOWS_LAYOUT),
+		] {
+			let (fields, literals) = bindgen_layout(bindings);
+			assert_eq!(fields, FIELDS, "{name}");
+			assert_eq!(literals[..2], [size, align], "{name}");
+			assert_eq!(literals[2..], offsets, "{name}");
+		}
+	}
+
+	#[test]
+	fn ugc_details_layout() {
+		use std::mem::offset_of;
+		use sys::SteamUGCDetails_t as D;
+
+		let (size, align, offsets) = LAYOUT;
+		assert_eq!(mem::size_of::<D>(), size);
+		assert_eq!(mem::align_of::<D>(), align);
+		assert_eq!([
+			offset_of!(D, m_nPublishedFileId),
+			offset_of!(D, m_eResult),
+			offset_of!(D, m_eFileType),
+			offset_of!(D, m_nCreatorAppID),
+			offset_of!(D, m_nConsumerAppID),
+			offset_of!(D, m_rgchTitle),
+			offset_of!(D, m_rgchDescription),
+			offset_of!(D, m_ulSteamIDOwner),
+			offset_of!(D, m_rtimeCreated),
+			offset_of!(D, m_rtimeUpdated),
+			offset_of!(D, m_rtimeAddedToUserList),
+			offset_of!(D, m_eVisibility),
+			offset_of!(D, m_bBanned),
+			offset_of!(D, m_bAcceptedForUse),
+			offset_of!(D, m_bTagsTruncated),
+			offset_of!(D, m_rgchTags),
+			offset_of!(D, m_hFile),
+			offset_of!(D, m_hPreviewFile),
+			offset_of!(D, m_pchFileName),
+			offset_of!(D, m_nFileSize),
+			offset_of!(D, m_nPreviewFileSize),
+			offset_of!(D, m_rgchURL),
+			offset_of!(D, m_unVotesUp),
+			offset_of!(D, m_unVotesDown),
+			offset_of!(D, m_flScore),
+			offset_of!(D, m_unNumChildren),
+		], offsets);
+	}
+
+	#[test]
+	fn query_result_from_raw() {
+		fn chars<const N: usize>(s: &str) -> [c_char; N] {
+			let mut chars = [0 as c_char; N];
+			for (c, b) in chars.iter_mut().zip(s.bytes()) {
+				*c = b as c_char;
+			}
+			chars
+		}
+
+		let mut raw: sys::SteamUGCDetails_t = unsafe { mem::zeroed() };
+		raw.m_nPublishedFileId = 104533079;
+		raw.m_eResult = sys::EResult::k_EResultOK;
+		raw.m_eFileType = sys::EWorkshopFileType::k_EWorkshopFileTypeCollection;
+		raw.m_nCreatorAppID = 4000;
+		raw.m_nConsumerAppID = 0;
+		raw.m_rgchTitle = chars("Title");
+		raw.m_rgchDescription = chars("[b]Description[/b]");
+		raw.m_ulSteamIDOwner = 76561197960287930;
+		raw.m_rtimeCreated = 1;
+		raw.m_rtimeUpdated = 2;
+		raw.m_rtimeAddedToUserList = 3;
+		raw.m_eVisibility = sys::ERemoteStoragePublishedFileVisibility::k_ERemoteStoragePublishedFileVisibilityUnlisted;
+		raw.m_bBanned = true;
+		raw.m_bAcceptedForUse = true;
+		raw.m_bTagsTruncated = true;
+		raw.m_rgchTags = chars("Addon,Map,,Fun");
+		raw.m_hFile = 0x0123_4567_89AB_CDEF;
+		raw.m_hPreviewFile = sys::k_UGCHandleInvalid;
+		raw.m_pchFileName = chars("addon.gma");
+		raw.m_nFileSize = 1234;
+		raw.m_nPreviewFileSize = -1;
+		raw.m_rgchURL = chars("https://example.com/");
+		raw.m_unVotesUp = 10;
+		raw.m_unVotesDown = 5;
+		raw.m_flScore = 0.5;
+		raw.m_unNumChildren = 7;
+
+		let result = QueryResult::from_raw(&raw);
+		assert_eq!(result.published_file_id, PublishedFileId(104533079));
+		assert_eq!(result.file_type, FileType::Collection);
+		assert_eq!(result.creator_app_id, Some(AppId(4000)));
+		assert_eq!(result.consumer_app_id, None);
+		assert_eq!(result.title, "Title");
+		assert_eq!(result.description, "[b]Description[/b]");
+		assert_eq!(result.owner.raw(), 76561197960287930);
+		assert_eq!((result.time_created, result.time_updated, result.time_added_to_user_list), (1, 2, 3));
+		assert_eq!(result.visibility, PublishedFileVisibility::Unlisted);
+		assert!(result.banned && result.accepted_for_use && result.tags_truncated);
+		assert_eq!(result.tags, ["Addon", "Map", "Fun"]);
+		assert_eq!(result.file, Some(UGCHandle(0x0123_4567_89AB_CDEF)));
+		assert_eq!(result.file_name, "addon.gma");
+		assert_eq!(result.file_size, 1234);
+		assert_eq!(result.preview_file, None);
+		assert_eq!(result.preview_file_size, 0);
+		assert_eq!(result.url, "https://example.com/");
+		assert_eq!(result.raw_tags, b"Addon,Map,,Fun");
+		assert_eq!(result.raw_url, b"https://example.com/");
+		assert_eq!((result.num_upvotes, result.num_downvotes, result.score, result.num_children), (10, 5, 0.5, 7));
+	}
+
+	#[test]
+	fn raw_enum_values() {
+		for preview_type in [ItemPreviewType::Image, ItemPreviewType::EnvironmentMapLatLong, ItemPreviewType::Other(99)] {
+			assert_eq!(ItemPreviewType::from_raw(preview_type.to_raw()), preview_type);
+		}
+		for descriptor in [ContentDescriptor::NudityOrSexualContent, ContentDescriptor::AnyMatureContent, ContentDescriptor::Other(99)] {
+			assert_eq!(ContentDescriptor::from_raw(descriptor.to_raw()), descriptor);
+		}
+	}
+
+	#[test]
+	fn query_result_unknown_values() {
+		let mut raw: sys::SteamUGCDetails_t = unsafe { mem::zeroed() };
+		raw.m_eResult = sys::EResult::k_EResultOK;
+		raw.m_rgchTitle[..3].copy_from_slice(&[b'a' as c_char, 0xFF_u8 as c_char, b'b' as c_char]);
+		unsafe { std::ptr::write_unaligned(std::ptr::addr_of_mut!(raw.m_eVisibility) as *mut u32, 99) };
+		unsafe { std::ptr::write_unaligned(std::ptr::addr_of_mut!(raw.m_eFileType) as *mut u32, 99) };
+
+		let result = QueryResult::from_raw(&raw);
+		assert_eq!(result.title, "a\u{FFFD}b");
+		assert_eq!(result.raw_title, b"a\xFFb");
+		assert_eq!(result.visibility, PublishedFileVisibility::Private);
+		assert_eq!(result.file_type, FileType::Community);
+	}
+}